@@ -16,8 +16,6 @@ extern crate tiberius;
 
 extern crate toml;
 
-extern crate reroute;
-
 extern crate jwt;
 
 extern crate futures_state_stream;
@@ -25,22 +23,29 @@ extern crate futures_state_stream;
 extern crate slug;
 
 use hyper::server::{Request, Response};
-use reroute::{Captures};
+use hyper::header::Location;
+use router::Params;
+use query::Query;
 
 use slug::slugify;
+use rand::Rng;
+
+use settings::SlugStrategy;
 
 use super::*;
 
-static ARTICLE_SELECT : &'static str = r#"
+pub static ARTICLE_SELECT : &'static str = r#"
   SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
                 (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
                 (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = @id ) as FavoritesCount,
                 (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
-				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=@id)  as Tags
+				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=@id)  as Tags,
+                Articles.[CurrentStatus], Articles.PublishedAt, Articles.PublishAt, CONVERT(bigint, Articles.[Version])
                 FROM Articles INNER JOIN Users on Author=Users.Id  WHERE Articles.Id = @id
+                AND ( Articles.[CurrentStatus] NOT IN ('draft', 'scheduled') OR Articles.Author = @logged )
 "#;
 
-fn get_simple_article_from_row( row : tiberius::query::QueryRow ) -> Option<Article> {
+pub fn get_simple_article_from_row( row : tiberius::query::QueryRow ) -> Option<Article> {
     let slug : &str = row.get(0);
     let title : &str = row.get(1);
     let description : &str = row.get(2);
@@ -55,7 +60,11 @@ fn get_simple_article_from_row( row : tiberius::query::QueryRow ) -> Option<Arti
     let favorites_count: i32 = row.get(10);
     let personal_favorite_count: i32 = row.get(11);
     let favorited : bool = personal_favorite_count > 0;
-    let tags_combined : &str = row.get(12);
+    // STRING_AGG over no rows is NULL for untagged articles
+    let tags_combined : Option<&str> = row.get(12);
+    let status : &str = row.get(13);
+    let published_at : Option<chrono::NaiveDateTime> = row.get(14);
+    let publish_at : Option<chrono::NaiveDateTime> = row.get(15);
 
     let profile = Profile{ username: user_name.to_string(), bio:bio.map(|s| s.to_string()),
         image:image.map(|s| s.to_string()), following : following };
@@ -65,47 +74,148 @@ fn get_simple_article_from_row( row : tiberius::query::QueryRow ) -> Option<Arti
         title: title.to_string(),
         description : description.to_string(),
         body : body.to_string(),
-        tagList: tags_combined.split(",").map(|q| q.to_string()).collect(),
+        tagList: tags_combined.map(|tags| tags.split(",").map(|q| q.to_string()).collect()).unwrap_or_else(Vec::new),
         createdAt: created,
         updatedAt: updated,
+        status: status.to_string(),
+        publishedAt: published_at,
+        publishAt: publish_at,
         favorited : favorited,
         favoritesCount : favorites_count,
-        author : profile                                    
+        author : profile,
+        source : None
     };
     Some(result)
 }
-fn get_article_from_row( row : tiberius::query::QueryRow ) -> Option<CreateArticleResult> {
+
+/// Rows of the paged list queries end with the total count and the article Id, which is NULL when the page is empty.
+fn get_paged_article_from_row( row : tiberius::query::QueryRow ) -> (Option<(i32, Article)>, i32) {
+    let count : i32 = row.get(16);
+    let id : Option<i32> = row.get(17);
+    match id {
+        Some(id) => (get_simple_article_from_row(row).map(|article| (id, article)), count),
+        None => (None, count),
+    }
+}
+
+/// Feed rows go on with whether the author is followed and which followed tags the article has.
+fn get_feed_article_from_row( row : tiberius::query::QueryRow ) -> (Option<(i32, Article)>, i32) {
+    let followed_author : Option<i32> = row.get(18);
+    let followed_tags : Vec<String> = {
+        let tags : Option<&str> = row.get(19);
+        tags.map(|tags| tags.split(",").map(|q| q.to_string()).collect()).unwrap_or_else(Vec::new)
+    };
+    match get_paged_article_from_row(row) {
+        (Some((id, mut article)), count) => {
+            article.source = Some(FeedSource{ followedAuthor: followed_author == Some(1), followedTags: followed_tags });
+            (Some((id, article)), count)
+        }
+        (None, count) => (None, count),
+    }
+}
+
+pub fn get_article_from_row( row : tiberius::query::QueryRow ) -> Option<CreateArticleResult> {
     Some(CreateArticleResult{ article:get_simple_article_from_row(row).unwrap() })
 }
-pub fn create_article_handler(req: Request, res: Response, _: Captures) {
+
+/// An `ARTICLE_SELECT` row with the version for its `ETag`.
+fn get_versioned_article_from_row( row : tiberius::query::QueryRow ) -> Option<(i64, CreateArticleResult)> {
+    let version : i64 = row.get(16);
+    get_article_from_row(row).map(|article| (version, article))
+}
+
+/// Id of the written article and whether its `If-Match` held.
+fn get_write_outcome_from_row( row : tiberius::query::QueryRow ) -> Option<(i32, bool)> {
+    let id : i32 = row.get(0);
+    let matched : i32 = row.get(1);
+    Some((id, matched == 1))
+}
+static MAX_SLUG_BASE_LENGTH : usize = 240;
+static SLUG_SUFFIX_CHARACTERS : &'static [u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+static SLUG_SUFFIX_LENGTH : usize = 6;
+
+/// Slug for `title` before `dbo.UniqueSlug` makes it unique, and whether it is reserved.
+fn base_slug(title: &str, reserved: &[String]) -> (String, bool) {
+    let mut slug = slugify(title);
+    if slug.len() > MAX_SLUG_BASE_LENGTH {
+        slug.truncate(MAX_SLUG_BASE_LENGTH);
+        while slug.ends_with('-') {
+            slug.pop();
+        }
+    }
+    if slug.is_empty() {
+        slug = "article".to_string();
+    }
+    let is_reserved = reserved.contains(&slug);
+    (slug, is_reserved)
+}
+
+/// Random id appended to a taken slug with `SlugStrategy::Random`, empty with `Counter`.
+fn slug_suffix(strategy: &SlugStrategy) -> String {
+    match *strategy {
+        SlugStrategy::Counter => String::new(),
+        SlugStrategy::Random => {
+            let mut rng = rand::thread_rng();
+            (0..SLUG_SUFFIX_LENGTH)
+                .map(|_| *rng.choose(SLUG_SUFFIX_CHARACTERS).unwrap() as char)
+                .collect()
+        }
+    }
+}
+
+pub fn create_article_handler(req: Request, res: Response, _: Params) {
     let (body, logged_in_user_id) = prepare_parameters(req);
     
     let create_article : CreateArticle = serde_json::from_str(&body).unwrap();     
     let title : &str = &create_article.article.title;
     let description : &str = &create_article.article.description;
     let body : &str = &create_article.article.body;
-    let tag_list : Vec<String> = create_article.article.tagList.unwrap_or(Vec::new());
-    let slug : &str = &slugify(title);
+    let tag_list = match tag::normalize_all(&SETTINGS.tags, &create_article.article.tagList.unwrap_or(Vec::new())) {
+        Ok(tag_list) => tag_list,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let (slug, reserved) = base_slug(title, &SETTINGS.articles.reserved_slugs);
+    let slug : &str = &slug;
+    let reserved : i32 = if reserved { 1 } else { 0 };
+    let slug_pattern : &str = &escape_like(slug);
+    let suffix : &str = &slug_suffix(&SETTINGS.articles.slug_strategy);
     let tags : &str = &tag_list.join(",");
+    let (status, publish_at) = match article_status(
+            create_article.article.status.as_ref().map(|s| s.as_str()),
+            create_article.article.publishAt.as_ref().map(|s| s.as_str())) {
+        Ok((status, publish_at)) => (status.unwrap_or(ArticleStatus::Published).name(), publish_at),
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let publish_at : &str = &publish_at;
 
-    process(
+    // the range lock on the slug makes a concurrent article with the same title wait for this one
+    process_with(
         res,
-        r#"insert into Tags (Tag) SELECT EmployeeID = Item FROM dbo.SplitNVarchars(@P6, ',')  Except select Tag from Tags;                            
-        INSERT INTO Articles (Title, [Description], Body, Created, Author, Slug) Values (@P1, @P2, @P3, getdate(), @P4, @P5);
+        r#"BEGIN TRANSACTION;
+        insert into Tags (Tag) SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P6, ',')  Except select Tag from Tags;                            
+        -- @taken is never read: the count only takes a range lock on the slugs starting like ours,
+        -- so a concurrent write can't pick the same suffix in dbo.UniqueSlug before we commit
+        DECLARE @taken int = (SELECT COUNT(*) FROM Articles WITH (UPDLOCK, HOLDLOCK) WHERE Slug LIKE @P7 + '%');
+        INSERT INTO Articles (Title, [Description], Body, Created, Author, Slug, [Status], PublishedAt, PublishAt)
+            Values (@P1, @P2, @P3, GETUTCDATE(), @P4, dbo.UniqueSlug(@P5, @P8, @P9, NULL), @P10,
+                CASE WHEN @P10 IN ('draft', 'scheduled') THEN NULL ELSE GETUTCDATE() END,
+                CASE WHEN @P10 = 'scheduled' THEN CONVERT(datetime, @P11, 126) END);
         DECLARE @id int = SCOPE_IDENTITY(); DECLARE @logged int = @P4;
-        insert into [ArticleTags] (ArticleId, TagId) SELECT @id, Id From Tags WHERE Tag IN (SELECT EmployeeID = Item FROM dbo.SplitNVarchars(@P6, ','));
+        insert into [ArticleTags] (ArticleId, TagId) SELECT @id, Id From Tags WHERE Tag IN (SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P6, ','));
+        EXEC dbo.AddArticleRevision @id, @logged;
+        COMMIT;
         "#, 
         ARTICLE_SELECT,
         get_article_from_row,
-        &[&title, &description, &body, &logged_in_user_id, &slug,&tags,]
+        &[&title, &description, &body, &logged_in_user_id, &slug,&tags, &slug_pattern, &suffix, &reserved, &status, &publish_at],
+        |created: &CreateArticleResult| search::refresh(&[created.article.slug.as_str()])
     );
 }
 
-fn process_and_return_article(name : &str, req: Request, res: Response, c: Captures, sql_command : &'static str ) {
+fn process_and_return_article(name : &str, req: Request, res: Response, p: Params, sql_command : &'static str ) {
     let (_, logged_id) = prepare_parameters( req );
     
-    let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "").replace("/favorite", "");
+    let slug = p.get("slug");
     println!("{} slug: '{}'", name, slug);
     println!("logged_id: {}", logged_id);
 
@@ -114,179 +224,522 @@ fn process_and_return_article(name : &str, req: Request, res: Response, c: Captu
         sql_command,
         ARTICLE_SELECT,
         get_article_from_row,
-        &[&(slug.as_str()), &(logged_id)]
+        &[&slug, &(logged_id)]
     ); 
 }
 
-pub fn favorite_article_handler(req: Request, res: Response, c: Captures) {
-    process_and_return_article("favorite_article_handler", req, res, c, "declare @id int; select TOP(1) @id = id from Articles where Slug = @P1 ORDER BY 1; DECLARE @logged int = @P2;
+pub fn favorite_article_handler(req: Request, res: Response, p: Params) {
+    process_and_return_article("favorite_article_handler", req, res, p, "declare @id int = dbo.VisibleArticleId(@P1, @P2); DECLARE @logged int = @P2;
                 INSERT INTO [dbo].[FavoritedArticles]
 	            ([ArticleId],
 	            [UserId])
-	            VALUES (@id,@P2)");              
+	            SELECT @id, @P2 WHERE @id IS NOT NULL");              
 }
 
-pub fn unfavorite_article_handler(req: Request, res: Response, c: Captures) {
-    process_and_return_article("unfavorite_article_handler", req, res, c, "declare @id int; DECLARE @logged int = @P2;
-                select TOP(1) @id = id from Articles where Slug = @P1 ORDER BY 1;
+pub fn unfavorite_article_handler(req: Request, res: Response, p: Params) {
+    process_and_return_article("unfavorite_article_handler", req, res, p, "declare @id int = dbo.VisibleArticleId(@P1, @P2); DECLARE @logged int = @P2;
                 DELETE TOP(1) FROM FavoritedArticles WHERE ArticleId = @id AND UserId = @P2;
                 ");
 }
 
 fn articles_result( _ : ArticlesResult ) {}
 
-pub fn feed_handler(req: Request, res: Response, c: Captures) {
+/// Articles of followed authors and with followed tags, newest first.
+pub fn feed_handler(req: Request, res: Response, p: Params) {
     let (_, logged_id) = prepare_parameters( req );
 
-    let caps = c.unwrap();
-    let url_params = &caps[0].replace("/api/articles/feed?", "");
-
-    println!("feed_handler url_params:'{}'", url_params);
-
-    let parsed_params: Vec<&str> = url_params.split('&').collect();
-
-    let mut limit :i32 = 20;
-    let mut offset :i32 = 0;
-
-    for param in &parsed_params {
-        let name_value: Vec<&str> = param.split('=').collect();
+    let query = p.query();
+    println!("feed_handler query:'{:?}'", query);
 
-        if name_value[0] == "offset" {
-            offset = name_value[1].parse::<i32>().unwrap();
-        }
-        else if name_value[0] == "limit" {
-            limit = name_value[1].parse::<i32>().unwrap();
-        }
-        ;
-    }    
+    let pager = match cursor::Pager::from_query(query, "articles") {
+        Ok(pager) => pager,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let (offset, fetch, after, before) = (pager.offset(), pager.fetch(), pager.after(), pager.before());
 
-    process_container(
+    process_paged_container(
         res,
         r#"declare @logged int = @p1;
+declare @after int = @p4;
+declare @before int = @p5;
         "#,
-        r#"SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
+        r#"WITH Filtered AS (
+                SELECT Articles.Id FROM Articles
+				WHERE Articles.[CurrentStatus] = 'published' AND (
+				    Author IN ( SELECT FollowingId FROM Followings WHERE FollowerId = @logged ) 
+				    OR Articles.Id IN ( SELECT ArticleId FROM ArticleTags WHERE TagId IN ( SELECT TagId FROM TagFollowings WHERE UserId = @logged ) ) )
+            ), PageIds AS (
+                SELECT Id FROM Filtered WHERE (@after = 0 OR Id < @after) AND (@before = 0 OR Id > @before)
+                order by CASE WHEN @before = 0 THEN -Id ELSE Id END OFFSET @p2 ROWS FETCH NEXT @p3 ROWS Only
+            )
+            SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
                 (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
                 (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount,
                 (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
-				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags
-                FROM Articles INNER JOIN Users on Author=Users.Id  
-				WHERE Author IN ( SELECT FollowingId FROM Followings WHERE FollowerId = @logged ) 
-order by Articles.Id DESC OFFSET @p2 ROWS FETCH NEXT @p3 ROWS Only"#,
-        get_simple_article_from_row,
+				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags,
+                Articles.[CurrentStatus], Articles.PublishedAt, Articles.PublishAt,
+                Counted.ArticlesCount, Articles.Id,
+                CASE WHEN Author IN ( SELECT FollowingId FROM Followings WHERE FollowerId = @logged ) THEN 1 ELSE 0 END AS FollowedAuthor,
+                (SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id
+                    where ArticleId=Articles.Id AND Tags.Id IN ( SELECT TagId FROM TagFollowings WHERE UserId = @logged ))  as FollowedTags
+                FROM (SELECT COUNT(*) AS ArticlesCount FROM Filtered) AS Counted
+                LEFT JOIN PageIds ON 1 = 1
+                LEFT JOIN Articles ON Articles.Id = PageIds.Id
+                LEFT JOIN Users on Author=Users.Id  
+order by Articles.Id DESC"#,
+        get_feed_article_from_row,
         articles_result,
-        &[&logged_id, &offset, &limit]
+        &pager,
+        &[&logged_id, &offset, &fetch, &after, &before]
     );
 }
 
-pub fn list_article_handler(req: Request, res: Response, c: Captures) {
-    let (_, logged_id) = prepare_parameters( req );
-
-    let caps = c.unwrap();
-    let url_params = &caps[0].replace("/api/articles?", "");
+/// Who sees an article: a draft only its author, an unlisted one whoever has the link,
+/// and only published ones are listed. A scheduled article is a draft until its `publishAt`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ArticleStatus {
+    Draft,
+    Published,
+    Unlisted,
+    Scheduled,
+}
 
-    println!("list_article_handler url_params:'{}'", url_params);
+impl ArticleStatus {
+    fn parse(value: &str) -> Result<ArticleStatus, String> {
+        match value {
+            "draft" => Ok(ArticleStatus::Draft),
+            "published" => Ok(ArticleStatus::Published),
+            "unlisted" => Ok(ArticleStatus::Unlisted),
+            "scheduled" => Ok(ArticleStatus::Scheduled),
+            _ => Err(format!("status '{}' is not one of draft, published, unlisted, scheduled", value)),
+        }
+    }
 
-    let parsed_params: Vec<&str> = url_params.split('&').collect();
+    fn name(&self) -> &'static str {
+        match *self {
+            ArticleStatus::Draft => "draft",
+            ArticleStatus::Published => "published",
+            ArticleStatus::Unlisted => "unlisted",
+            ArticleStatus::Scheduled => "scheduled",
+        }
+    }
+}
 
-    let mut limit :i32 = 20;
-    let mut offset :i32 = 0;
-    let mut tag = "";
-    let mut author = "";
-    let mut favorited = "";
+/// Resolves `status` and `publishAt` of a written article: `publishAt` implies `scheduled`
+/// and has to be in the future. Returns the status, if any, and `publishAt` formatted for SQL.
+fn article_status(status: Option<&str>, publish_at: Option<&str>) -> Result<(Option<ArticleStatus>, String), String> {
+    let status = match status {
+        Some(status) => Some(ArticleStatus::parse(status)?),
+        None => None,
+    };
+    let publish_at = match publish_at {
+        Some(publish_at) => Some(parse_date("publishAt", publish_at, false)?),
+        None => None,
+    };
+    match (status, publish_at) {
+        (None, Some(publish_at)) | (Some(ArticleStatus::Scheduled), Some(publish_at)) => {
+            if publish_at <= chrono::Utc::now().naive_utc() {
+                return Err("publishAt must be in the future".to_string());
+            }
+            Ok((Some(ArticleStatus::Scheduled), format_sql_date(Some(publish_at))))
+        },
+        (Some(ArticleStatus::Scheduled), None) => Err("status scheduled requires publishAt".to_string()),
+        (Some(status), Some(_)) => Err(format!("publishAt can not be combined with status {}", status.name())),
+        (status, None) => Ok((status, String::new())),
+    }
+}
 
-    for param in &parsed_params {
-        let name_value: Vec<&str> = param.split('=').collect();
+/// Order of `GET /api/articles`, `sort=newest` by default.
+#[derive(Debug, PartialEq)]
+enum ArticleSort {
+    Newest,
+    Oldest,
+    MostFavorited,
+    Updated,
+}
 
-        if name_value[0] == "tag" {
-            tag = name_value[1];
-        } 
-        else if name_value[0] == "author" {
-            author = name_value[1];
+impl ArticleSort {
+    fn parse(value: &str) -> Result<ArticleSort, String> {
+        match value {
+            "newest" => Ok(ArticleSort::Newest),
+            "oldest" => Ok(ArticleSort::Oldest),
+            "mostFavorited" => Ok(ArticleSort::MostFavorited),
+            "updated" => Ok(ArticleSort::Updated),
+            _ => Err(format!("sort '{}' is not one of newest, oldest, mostFavorited, updated", value)),
         }
-        else if name_value[0] == "favorited" {
-            favorited = name_value[1];
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            ArticleSort::Newest => "newest",
+            ArticleSort::Oldest => "oldest",
+            ArticleSort::MostFavorited => "mostFavorited",
+            ArticleSort::Updated => "updated",
         }
-        else if name_value[0] == "offset" {
-            offset = name_value[1].parse::<i32>().unwrap();
+    }
+
+    /// Multiplier making `Id * direction` ascend in listing order; ties of the other sorts go newest first.
+    fn direction(&self) -> i32 {
+        if *self == ArticleSort::Oldest { 1 } else { -1 }
+    }
+
+    fn ordered_by_id(&self) -> bool {
+        *self == ArticleSort::Newest || *self == ArticleSort::Oldest
+    }
+}
+
+/// Filters and order of `GET /api/articles`; every filter is optional and they all combine.
+#[derive(Debug, PartialEq)]
+struct ArticleListing {
+    tags: Vec<String>,
+    /// `tagMatch=all` requires every `tag`, the default `any` one of them.
+    match_all_tags: bool,
+    excluded_tags: Vec<String>,
+    authors: Vec<String>,
+    favorited: String,
+    since: Option<chrono::NaiveDateTime>,
+    /// Exclusive.
+    until: Option<chrono::NaiveDateTime>,
+    sort: ArticleSort,
+    /// Anything but `published` lists the logged in user's own articles.
+    status: ArticleStatus,
+}
+
+/// A date (`2017-08-26`) or date and time (`2017-08-26T22:35:28`, with an RFC 3339 offset converted to UTC).
+/// A bare date in `until` includes that whole day.
+fn parse_date_param(query: &Query, name: &str, end_of_day: bool) -> Result<Option<chrono::NaiveDateTime>, String> {
+    match query.get(name) {
+        Some(value) => parse_date(name, value, end_of_day).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn parse_date(name: &str, value: &str, end_of_day: bool) -> Result<chrono::NaiveDateTime, String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end_of_day { date.succ_opt() } else { Some(date) };
+        return date.map(|date| date.and_hms(0, 0, 0)).ok_or_else(|| format!("{} '{}' is out of range", name, value));
+    }
+    if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.naive_utc());
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .map_err(|_| format!("{} '{}' is not a date", name, value))
+}
+
+fn distinct_values(query: &Query, name: &str) -> Vec<String> {
+    let mut values : Vec<String> = Vec::new();
+    for value in query.get_all(name) {
+        if !values.iter().any(|v| v == value) {
+            values.push(value.to_string());
         }
-        else if name_value[0] == "limit" {
-            limit = name_value[1].parse::<i32>().unwrap();
+    }
+    values
+}
+
+impl ArticleListing {
+    fn from_query(query: &Query) -> Result<ArticleListing, String> {
+        let match_all_tags = match query.get("tagMatch").unwrap_or("any") {
+            "any" => false,
+            "all" => true,
+            other => return Err(format!("tagMatch '{}' is not one of any, all", other)),
+        };
+        let since = parse_date_param(query, "since", false)?;
+        let until = parse_date_param(query, "until", true)?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since >= until {
+                return Err("since must be before until".to_string());
+            }
         }
-        ;
+        Ok(ArticleListing {
+            tags: distinct_values(query, "tag"),
+            match_all_tags: match_all_tags,
+            excluded_tags: distinct_values(query, "excludeTag"),
+            authors: distinct_values(query, "author"),
+            favorited: query.get("favorited").unwrap_or("").to_string(),
+            since: since,
+            until: until,
+            sort: ArticleSort::parse(query.get("sort").unwrap_or("newest"))?,
+            status: ArticleStatus::parse(query.get("status").unwrap_or("published"))?,
+        })
     }
+}
 
-    process_container(
-        res,
+fn format_sql_date(date: Option<chrono::NaiveDateTime>) -> String {
+    date.map(|d| d.format("%Y-%m-%dT%H:%M:%S%.3f").to_string()).unwrap_or_default()
+}
+
+pub fn list_article_handler(req: Request, res: Response, p: Params) {
+    let conditions = conditional::Conditions::from_headers(&req.headers);
+    let (_, logged_id) = prepare_parameters( req );
+
+    let query = p.query();
+    println!("list_article_handler query:'{:?}'", query);
+
+    let listing = match ArticleListing::from_query(query) {
+        Ok(listing) => listing,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    if listing.status != ArticleStatus::Published && logged_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required to list drafts and unlisted articles");
+    }
+    let pager = if listing.sort.ordered_by_id() {
+        cursor::Pager::from_query(query, "articles")
+    } else {
+        cursor::Pager::offset_only(query, "articles")
+    };
+    let pager = match pager {
+        Ok(pager) => pager,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let (offset, fetch, after, before) = (pager.offset(), pager.fetch(), pager.after(), pager.before());
+
+    // lists go to OPENJSON, user names and tags may contain anything
+    let tags = serde_json::to_string(&tag::normalize_filter(&SETTINGS.tags, &listing.tags)).unwrap();
+    let authors = serde_json::to_string(&listing.authors).unwrap();
+    let excluded_tags = serde_json::to_string(&tag::normalize_filter(&SETTINGS.tags, &listing.excluded_tags)).unwrap();
+    let favorited : &str = &listing.favorited;
+    let match_all_tags : i32 = if listing.match_all_tags { 1 } else { 0 };
+    let since = format_sql_date(listing.since);
+    let until = format_sql_date(listing.until);
+    let sort = listing.sort.name();
+    let direction = listing.sort.direction();
+    let status = listing.status.name();
+
+    let page = fetch_paged_container(
         r#"declare @logged int = @p1;
-declare @tag nvarchar(max) = @p4;
-declare @username nvarchar(max) = @p5;
+declare @tags nvarchar(max) = @p4;
+declare @authors nvarchar(max) = @p5;
 declare @favorited nvarchar(max) = @p6;        
+declare @after int = @p7;
+declare @before int = @p8;
+declare @matchAllTags int = @p9;
+declare @excludedTags nvarchar(max) = @p10;
+declare @since nvarchar(max) = @p11;
+declare @until nvarchar(max) = @p12;
+declare @sort nvarchar(max) = @p13;
+declare @dir int = @p14;
+declare @status nvarchar(10) = @p15;
+declare @tagCount int = (SELECT COUNT(DISTINCT dbo.CanonicalTag(value)) FROM OPENJSON(@tags));
+declare @authorCount int = (SELECT COUNT(*) FROM OPENJSON(@authors));
         "#,
-        r#"SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
-        (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
-        (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount,
-        (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
-		(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags
-        FROM Articles INNER JOIN Users on Author=Users.Id  
-		
-		WHERE Articles.Id in ( SELECT ArticleId from ArticleTags WHERE TagId IN ( Select Id from Tags where Tag = @tag OR LEN(@tag) = 0 )  ) 
-		/*inner join ArticleTags on ArticleTags.ArticleId = Articles.id 
-		inner join Tags on Tags.Id = ArticleTags.TagId and Tag = @tag OR LEN(@tag) = 0*/
+        r#"WITH Filtered AS (
+        SELECT Articles.Id, COALESCE(Articles.Updated, Articles.Created) AS LastChanged,
+        (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount
+        FROM Articles
 		
-		AND Articles.Author in ( SELECT Id from Users where UserName = @username OR LEN(@username) = 0 ) 
+		WHERE Articles.[CurrentStatus] = @status AND ( @status = 'published' OR Articles.Author = @logged )
 
-		AND Articles.Id in ( SELECT ArticleId from FavoritedArticles WHERE UserId IN ( SELECT Id from Users where UserName = @favorited OR LEN(@favorited) = 0 )  ) 
+		AND ( @tagCount = 0 OR ( SELECT COUNT(DISTINCT Tags.Id) FROM ArticleTags inner join Tags on Tags.Id = ArticleTags.TagId
+		    WHERE ArticleTags.ArticleId = Articles.Id AND Tag IN ( SELECT dbo.CanonicalTag(value) FROM OPENJSON(@tags) ) )
+		    >= CASE WHEN @matchAllTags = 1 THEN @tagCount ELSE 1 END )
 
-order by Articles.Id DESC OFFSET @p2 ROWS FETCH NEXT @p3 ROWS Only"#,
-        get_simple_article_from_row,
+		AND Articles.Id NOT IN ( SELECT ArticleId from ArticleTags inner join Tags on Tags.Id = ArticleTags.TagId
+		    WHERE Tag IN ( SELECT dbo.CanonicalTag(value) FROM OPENJSON(@excludedTags) ) )
+		
+		AND ( @authorCount = 0 OR Articles.Author in ( SELECT Id from Users where UserName IN ( SELECT value FROM OPENJSON(@authors) ) ) )
+
+		AND ( LEN(@favorited) = 0 OR Articles.Id in ( SELECT ArticleId from FavoritedArticles WHERE UserId IN ( SELECT Id from Users where UserName = @favorited ) ) )
+
+		AND ( LEN(@since) = 0 OR Articles.Created >= CONVERT(datetime2, @since, 126) )
+		AND ( LEN(@until) = 0 OR Articles.Created < CONVERT(datetime2, @until, 126) )
+        ), Ranked AS (
+        SELECT Id, ROW_NUMBER() OVER ( ORDER BY
+            CASE WHEN @sort = 'mostFavorited' THEN FavoritesCount END DESC,
+            CASE WHEN @sort = 'updated' THEN LastChanged END DESC,
+            Id * @dir ) AS Position
+        FROM Filtered WHERE (@after = 0 OR Id * @dir > @after * @dir) AND (@before = 0 OR Id * @dir < @before * @dir)
+        ), PageIds AS (
+        SELECT Id, Position FROM Ranked
+        order by CASE WHEN @before = 0 THEN Position ELSE -Position END OFFSET @p2 ROWS FETCH NEXT @p3 ROWS Only
+        )
+        SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
+        (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
+        (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount,
+        (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
+		(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags,
+        Articles.[CurrentStatus], Articles.PublishedAt, Articles.PublishAt,
+        Counted.ArticlesCount, Articles.Id
+        FROM (SELECT COUNT(*) AS ArticlesCount FROM Filtered) AS Counted
+        LEFT JOIN PageIds ON 1 = 1
+        LEFT JOIN Articles ON Articles.Id = PageIds.Id
+        LEFT JOIN Users on Author=Users.Id  
+order by PageIds.Position"#,
+        get_paged_article_from_row,
         articles_result,
-        &[&logged_id, &offset, &limit, &tag, &author, &favorited]
-    );                       
+        &pager,
+        &[&logged_id, &offset, &fetch, &tags.as_str(), &authors.as_str(), &favorited, &after, &before,
+          &match_all_tags, &excluded_tags.as_str(), &since.as_str(), &until.as_str(), &sort, &direction, &status]
+    );
+    conditional::send_cached(res, &conditions, &conditional::ARTICLE_LIST_CACHE, None, None, &page);
 }
 
-pub fn get_article_handler(req: Request, res: Response, c: Captures) {
-    process_and_return_article(
-        "get_article_handler", req, res, c, 
-        "declare @id int; select TOP(1) @id = id from Articles where Slug = @P1 ORDER BY 1; 
-        DECLARE @logged int = @P2;");              
+/// An old slug of a renamed article answers with a permanent redirect to the current one.
+pub fn get_article_handler(req: Request, mut res: Response, p: Params) {
+    let conditions = conditional::Conditions::from_headers(&req.headers);
+    let (_, logged_id) = prepare_parameters( req );
+
+    let slug = p.get("slug");
+    println!("get_article_handler slug: '{}'", slug);
+
+    let found = fetch_rows(
+        "declare @id int = dbo.VisibleArticleId(@P1, @P2); DECLARE @logged int = @P2;",
+        ARTICLE_SELECT,
+        get_versioned_article_from_row,
+        &[&slug, &logged_id]
+    ).pop();
+    match found {
+        None => send_error(res, StatusCode::NotFound, "article not found"),
+        Some((_, ref found)) if found.article.slug != slug => {
+            *res.status_mut() = StatusCode::MovedPermanently;
+            res.headers_mut().set(Location(format!("/api/articles/{}", found.article.slug)));
+        }
+        Some((version, found)) => {
+            let last_modified = found.article.updatedAt.unwrap_or(found.article.createdAt);
+            conditional::send_cached(res, &conditions, &conditional::ARTICLE_CACHE, Some(version), Some(last_modified), &found);
+        }
+    }
 }
 
-pub fn update_article_handler(req: Request, res: Response, c: Captures) {
+/// Honors `If-Match` with the article's `ETag`, a write over a newer version is answered with 412.
+pub fn update_article_handler(req: Request, res: Response, p: Params) {
+    let (check_version, versions) = conditional::if_match(&req.headers);
+    let versions : &str = &versions;
     let (body, logged_id) = prepare_parameters(req);
 
-    let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "");
+    let slug = p.get("slug");
     println!("slug {}", &slug);
 
     let update_article : UpdateArticle = serde_json::from_str(&body).unwrap();     
     let title : &str = update_article.article.title.as_ref().map(|x| &**x).unwrap_or("");
     let body : &str = update_article.article.body.as_ref().map(|x| &**x).unwrap_or("");
     let description : &str = update_article.article.description.as_ref().map(|x| &**x).unwrap_or("");
-    let new_slug : &str = &slugify(title);
+    let (new_slug, reserved) = base_slug(title, &SETTINGS.articles.reserved_slugs);
+    let new_slug : &str = &new_slug;
+    let reserved : i32 = if reserved { 1 } else { 0 };
+    let slug_pattern : &str = &escape_like(new_slug);
+    let suffix : &str = &slug_suffix(&SETTINGS.articles.slug_strategy);
+
+    let detail = &update_article.article;
+    if detail.tagList.is_some() && (detail.addTags.is_some() || detail.removeTags.is_some()) {
+        return send_error(res, StatusCode::UnprocessableEntity, "tagList can not be combined with addTags or removeTags");
+    }
+    let replace_tags : i32 = if detail.tagList.is_some() { 1 } else { 0 };
+    let kept_tags = tag::normalize_all(&SETTINGS.tags, detail.tagList.as_ref().or(detail.addTags.as_ref()).unwrap_or(&Vec::new()));
+    let removed_tags = tag::normalize_all(&SETTINGS.tags, detail.removeTags.as_ref().unwrap_or(&Vec::new()));
+    let (kept_tags, removed_tags) = match (kept_tags, removed_tags) {
+        (Ok(kept_tags), Ok(removed_tags)) => (kept_tags.join(","), removed_tags.join(",")),
+        (Err(e), _) | (_, Err(e)) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let (kept_tags, removed_tags) : (&str, &str) = (&kept_tags, &removed_tags);
+    let (status, publish_at) = match article_status(detail.status.as_ref().map(|s| s.as_str()), detail.publishAt.as_ref().map(|s| s.as_str())) {
+        Ok((status, publish_at)) => (status.map_or("", |status| status.name()), publish_at),
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let publish_at : &str = &publish_at;
 
-    process(
-        res,
+    let outcome = fetch_rows(
         r#"
         declare @id int; select TOP(1) @id = id from Articles where Slug = @P1; 
         DECLARE @logged int = @P5;
+        DECLARE @dropped TABLE (TagId int);
+        BEGIN TRANSACTION;
+        DECLARE @matched int = (SELECT COUNT(*) FROM Articles WITH (UPDLOCK, HOLDLOCK) WHERE Id = @id
+            AND ( @P15 = 0 OR CONVERT(nvarchar(20), CONVERT(bigint, [Version])) IN (SELECT Item FROM dbo.SplitNVarchars(@P16, ',')) ));
+        DECLARE @owned int = (SELECT COUNT(*) FROM Articles WHERE Id = @id AND Author = @logged AND @matched = 1);
+        -- @taken is never read: the count only takes a range lock on the slugs starting like ours,
+        -- so a concurrent write can't pick the same suffix in dbo.UniqueSlug before we commit
+        DECLARE @taken int = (SELECT COUNT(*) FROM Articles WITH (UPDLOCK, HOLDLOCK) WHERE LEN(@P2) > 0 AND Slug LIKE @P7 + '%');
         UPDATE TOP(1) [dbo].[Articles] SET 
         [Title]=CASE WHEN(LEN(@P2)=0) THEN Title ELSE @P2 END,
         [Description]=CASE WHEN(LEN(@P3)=0) THEN Description ELSE @P3 END,
-        [Body]=CASE WHEN(LEN(@P4)=0) THEN Description ELSE @P4 END,
-        [Slug]=CASE WHEN(LEN(@P2)=0) THEN [Slug] ELSE @P6 END
-        WHERE [Id] = @id AND Author = @logged; 
+        [Body]=CASE WHEN(LEN(@P4)=0) THEN Body ELSE @P4 END,
+        [Slug]=CASE WHEN(LEN(@P2)=0) THEN [Slug] ELSE dbo.UniqueSlug(@P6, @P8, @P9, @id) END,
+        [Updated]=GETUTCDATE(),
+        [Status]=CASE WHEN(LEN(@P13)=0) THEN [Status] ELSE @P13 END,
+        [PublishedAt]=CASE WHEN(LEN(@P13)=0) THEN PublishedAt WHEN @P13 IN ('draft', 'scheduled') THEN NULL ELSE COALESCE(PublishedAt, GETUTCDATE()) END,
+        [PublishAt]=CASE WHEN(LEN(@P13)=0) THEN PublishAt WHEN @P13 = 'scheduled' THEN CONVERT(datetime, @P14, 126) ELSE NULL END
+        WHERE [Id] = @id AND @owned = 1; 
+        IF @owned = 1 EXEC dbo.AddArticleRevision @id, @logged;
+        DECLARE @slug nvarchar(250) = (SELECT Slug FROM Articles WHERE Id = @id);
+        INSERT INTO ArticleSlugHistory (Slug, ArticleId, Replaced) SELECT @P1, @id, GETUTCDATE()
+            WHERE @slug <> @P1 AND NOT EXISTS (SELECT 1 FROM ArticleSlugHistory WHERE Slug = @P1);
+        DELETE FROM ArticleSlugHistory WHERE Slug = @slug AND ArticleId = @id;
+        DELETE FROM ArticleTags OUTPUT deleted.TagId INTO @dropped
+            WHERE @owned = 1 AND ArticleId = @id AND (
+                (@P10 = 1 AND TagId NOT IN (SELECT Id FROM Tags WHERE Tag IN (SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P11, ','))))
+                OR TagId IN (SELECT Id FROM Tags WHERE Tag IN (SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P12, ','))));
+        insert into Tags (Tag) SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P11, ',') WHERE @owned = 1 Except select Tag from Tags;
+        insert into [ArticleTags] (ArticleId, TagId) SELECT @id, Id From Tags
+            WHERE @owned = 1 AND Tag IN (SELECT dbo.CanonicalTag(Item) FROM dbo.SplitNVarchars(@P11, ','))
+            AND Id NOT IN (SELECT TagId FROM ArticleTags WHERE ArticleId = @id);
+        DELETE FROM Tags WHERE Id IN (SELECT TagId FROM @dropped) AND Id NOT IN (SELECT TagId FROM ArticleTags)
+            AND Id NOT IN (SELECT TagId FROM TagAliases) AND Id NOT IN (SELECT TagId FROM TagFollowings);
+        COMMIT;
         "#, 
+        "SELECT @id, @matched WHERE @id IS NOT NULL",
+        get_write_outcome_from_row,
+        &[&slug, &title, &description, &body, &logged_id, &new_slug, &slug_pattern, &suffix, &reserved,
+          &replace_tags, &kept_tags, &removed_tags, &status, &publish_at, &check_version, &versions]
+    ).pop();
+    let (id, matched) = match outcome {
+        Some(outcome) => outcome,
+        None => return send_error(res, StatusCode::NotFound, "article not found"),
+    };
+
+    let updated = fetch_rows(
+        "DECLARE @id int = @P1; DECLARE @logged int = @P2;",
+        ARTICLE_SELECT,
+        get_versioned_article_from_row,
+        &[&id, &logged_id]
+    ).pop();
+    match updated {
+        Some((version, ref current)) if !matched => conditional::send_precondition_failed(res, version, current),
+        Some((version, updated)) => {
+            search::refresh(&[slug, updated.article.slug.as_str()]);
+            conditional::send_versioned(res, version, &updated);
+        }
+        None => send_error(res, StatusCode::NotFound, "article not found"),
+    }
+}
+
+/// Sets the status of one of the logged in user's articles; `publishedAt` is kept across unlisting
+/// and cleared when the article goes back to being a draft. Either cancels a pending `publishAt`.
+fn set_article_status(name: &str, req: Request, res: Response, p: Params, status: ArticleStatus) {
+    let (_, logged_id) = prepare_parameters( req );
+    if logged_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required");
+    }
+
+    let slug = p.get("slug");
+    println!("{} slug: '{}'", name, slug);
+    let status = status.name();
+
+    process_with(
+        res,
+        "declare @id int; select TOP(1) @id = id from Articles where Slug = @P1 AND Author = @P2; DECLARE @logged int = @P2;
+        UPDATE Articles SET [Status] = @P3,
+        PublishedAt = CASE WHEN @P3 = 'draft' THEN NULL ELSE COALESCE(PublishedAt, GETUTCDATE()) END,
+        PublishAt = NULL
+        WHERE Id = @id;",
         ARTICLE_SELECT,
         get_article_from_row,
-        &[&(slug.as_str()), &title, &description, &body, &logged_id, &new_slug]
+        &[&slug, &logged_id, &status],
+        |article: &CreateArticleResult| search::refresh(&[article.article.slug.as_str()])
     );
 }
 
-pub fn delete_article_handler(req: Request, res: Response, c: Captures) {
+pub fn publish_article_handler(req: Request, res: Response, p: Params) {
+    set_article_status("publish_article_handler", req, res, p, ArticleStatus::Published);
+}
+
+pub fn unpublish_article_handler(req: Request, res: Response, p: Params) {
+    set_article_status("unpublish_article_handler", req, res, p, ArticleStatus::Draft);
+}
+
+pub fn delete_article_handler(req: Request, res: Response, p: Params) {
     let (_, logged_id) = prepare_parameters( req );
 
-    let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "");
+    let slug = p.get("slug");
     println!("slug: {}", slug);
 
     process(
@@ -295,15 +748,16 @@ pub fn delete_article_handler(req: Request, res: Response, c: Captures) {
         DELETE FROM Comments WHERE ArticleId = @id;
         DELETE FROM FavoritedArticles WHERE ArticleId = @id;
         DELETE FROM ArticleTags WHERE ArticleId = @id;
+        DELETE FROM ArticleSlugHistory WHERE ArticleId = @id;
+        DELETE FROM ArticleRevisions WHERE ArticleId = @id;
         DELETE FROM Articles WHERE id = @id AND Author = @P2;",
         "SELECT 1",
         handle_row_none,
-        &[&(slug.as_str()),&(logged_id)]
+        &[&slug,&(logged_id)]
     );
+    search::refresh(&[slug]);
 }
 
-#[cfg(test)]
-use rand::Rng;
 
 #[cfg(test)]
 pub fn login_create_article(follow:bool) -> (std::string::String, std::string::String, std::string::String) {
@@ -448,6 +902,7 @@ fn list_article_test() {
 
     let articles : ArticlesResult = serde_json::from_str(&buffer).unwrap();       
     assert_eq!(articles.articles.len()>0, true);
+    assert_eq!(articles.articlesCount >= articles.articles.len() as i32, true);
 }
 
 #[cfg(test)]
@@ -468,6 +923,7 @@ fn unfollowed_feed_article_test() {
 
     let articles : ArticlesResult = serde_json::from_str(&buffer).unwrap();       
     assert_eq!(articles.articles.len()==0, true);
+    assert_eq!(articles.articlesCount, 0);
 }
 
 #[cfg(test)]
@@ -488,6 +944,7 @@ fn followed_feed_article_test() {
 
     let articles : ArticlesResult = serde_json::from_str(&buffer).unwrap();       
     assert_eq!(articles.articles.len()==1, true);
+    assert_eq!(articles.articlesCount, 1);
 }
 
 #[cfg(test)]
@@ -534,3 +991,617 @@ fn delete_article_test() {
         .unwrap();
     assert_eq!(res.status, hyper::Ok);
 }
+
+#[cfg(test)]
+#[test]
+fn list_article_invalid_limit_test() {
+    let client = Client::new();
+
+    let res = client.get("http://localhost:6767/api/articles?limit=ten")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+fn get_articles(url: &str) -> ArticlesResult {
+    let client = Client::new();
+
+    let mut res = client.get(url)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    serde_json::from_str(&buffer).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn list_article_cursor_test() {
+    login_create_article(false);
+    login_create_article(false);
+
+    let first = get_articles("http://localhost:6767/api/articles?tag=dragons&limit=1");
+    assert_eq!(first.articles.len(), 1);
+    assert_eq!(first.prevCursor, None);
+    let next_cursor = first.nextCursor.unwrap();
+
+    let second = get_articles(&format!("http://localhost:6767/api/articles?tag=dragons&limit=1&after={}", next_cursor));
+    assert_eq!(second.articles.len(), 1);
+    assert_ne!(second.articles[0].slug, first.articles[0].slug);
+    let prev_cursor = second.prevCursor.unwrap();
+
+    let back = get_articles(&format!("http://localhost:6767/api/articles?tag=dragons&limit=1&before={}", prev_cursor));
+    assert_eq!(back.articles[0].slug, first.articles[0].slug);
+
+    let client = Client::new();
+    let res = client.get("http://localhost:6767/api/articles?after=forged.cursor")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+pub fn create_article_with_tags(jwt: &str, tags: &[&str]) -> String {
+    let client = Client::new();
+
+    let title = format!( "How to ride your dragon {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000) );
+    let tag_list = serde_json::to_string(tags).unwrap();
+    let body = format!( r#"{{"article": {{"title": "{}","description": "Ever wonder how?","body": "Hold on tight",
+                "tagList": {}}}}}"#, title, tag_list);
+
+    let mut res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+
+    let create_result : CreateArticleResult = serde_json::from_str(&buffer).unwrap();   
+    assert_eq!(create_result.article.tagList.len(), tags.len());
+    create_result.article.slug
+}
+
+#[cfg(test)]
+#[test]
+fn list_article_filter_matrix_test() {
+    let client = Client::new();
+
+    let (author, author_email) = register_jacob();
+    let author_jwt = login_jacob(author_email, user::JACOB_PASSWORD.to_string());
+    let (fan, fan_email) = register_jacob();
+    let fan_jwt = login_jacob(fan_email, user::JACOB_PASSWORD.to_string());
+    let tag = format!("matrix-{}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+
+    // neither tagged nor favorited, was missing from every list before
+    let plain = create_article_with_tags(&author_jwt, &[]);
+    let tagged = create_article_with_tags(&author_jwt, &[&tag]);
+
+    let res = client.post(&format!("http://localhost:6767/api/articles/{}/favorite", tagged))
+        .header(Authorization(Bearer {token: fan_jwt}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let slugs = |filters: &str| -> Vec<String> {
+        get_articles(&format!("http://localhost:6767/api/articles?limit=100&{}", filters))
+            .articles.into_iter().map(|article| article.slug).collect()
+    };
+    let tag_filter = format!("tag={}", tag);
+    let author_filter = format!("author={}", author);
+    let fan_filter = format!("favorited={}", fan);
+
+    let all = slugs("");
+    assert!(all.contains(&plain));
+    assert!(all.contains(&tagged));
+
+    assert_eq!(slugs(&tag_filter), vec![tagged.clone()]);
+    assert_eq!(slugs(&author_filter), vec![tagged.clone(), plain.clone()]);
+    assert_eq!(slugs(&fan_filter), vec![tagged.clone()]);
+    assert_eq!(slugs(&format!("{}&{}", tag_filter, author_filter)), vec![tagged.clone()]);
+    assert_eq!(slugs(&format!("{}&{}", tag_filter, fan_filter)), vec![tagged.clone()]);
+    assert_eq!(slugs(&format!("{}&{}", author_filter, fan_filter)), vec![tagged.clone()]);
+    assert_eq!(slugs(&format!("{}&{}&{}", tag_filter, author_filter, fan_filter)), vec![tagged.clone()]);
+
+    // present but empty parameters do not filter
+    assert_eq!(slugs(&format!("{}&tag=&favorited=", author_filter)), vec![tagged.clone(), plain.clone()]);
+
+    // filters that match nothing
+    assert!(slugs(&format!("favorited={}", author)).is_empty());
+    assert!(slugs(&format!("{}&author={}", tag_filter, fan)).is_empty());
+    assert!(slugs("tag=no-such-tag-anywhere").is_empty());
+
+    let untagged = get_articles(&format!("http://localhost:6767/api/articles?{}", author_filter));
+    let untagged = untagged.articles.into_iter().find(|article| article.slug == plain).unwrap();
+    assert!(untagged.tagList.is_empty());
+    assert_eq!(untagged.favoritesCount, 0);
+}
+
+#[cfg(test)]
+#[test]
+fn article_listing_test() {
+    let listing = ArticleListing::from_query(&Query::parse("")).unwrap();
+    assert_eq!(listing.sort, ArticleSort::Newest);
+    assert!(listing.tags.is_empty() && listing.authors.is_empty() && !listing.match_all_tags);
+
+    let listing = ArticleListing::from_query(&Query::parse(
+        "tag=a&tag=b&tag=a&tagMatch=all&excludeTag=c&author=x&author=y&since=2017-08-01&until=2017-08-31&sort=oldest")).unwrap();
+    assert_eq!(listing.tags, vec!["a", "b"]);
+    assert!(listing.match_all_tags);
+    assert_eq!(listing.excluded_tags, vec!["c"]);
+    assert_eq!(listing.authors, vec!["x", "y"]);
+    assert_eq!(format_sql_date(listing.since), "2017-08-01T00:00:00.000");
+    assert_eq!(format_sql_date(listing.until), "2017-09-01T00:00:00.000");
+    assert_eq!(listing.sort, ArticleSort::Oldest);
+
+    let listing = ArticleListing::from_query(&Query::parse("since=2017-08-26T22:35:28%2B02:00")).unwrap();
+    assert_eq!(format_sql_date(listing.since), "2017-08-26T20:35:28.000");
+
+    assert_eq!(listing.status, ArticleStatus::Published);
+    let listing = ArticleListing::from_query(&Query::parse("status=draft")).unwrap();
+    assert_eq!(listing.status, ArticleStatus::Draft);
+
+    assert!(ArticleListing::from_query(&Query::parse("status=deleted")).is_err());
+    assert!(ArticleListing::from_query(&Query::parse("sort=popular")).is_err());
+    assert!(ArticleListing::from_query(&Query::parse("tagMatch=some")).is_err());
+    assert!(ArticleListing::from_query(&Query::parse("since=yesterday")).is_err());
+    assert!(ArticleListing::from_query(&Query::parse("since=2017-09-01&until=2017-08-01")).is_err());
+    assert!(ArticleListing::from_query(&Query::parse("until=9999-12-31")).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn list_article_tags_and_sort_test() {
+    let client = Client::new();
+
+    let (author, author_email) = register_jacob();
+    let jwt = login_jacob(author_email, user::JACOB_PASSWORD.to_string());
+    let unique = format!("{}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+    let (red, blue, green) = (format!("red-{}", unique), format!("blue-{}", unique), format!("green-{}", unique));
+
+    let first = create_article_with_tags(&jwt, &[&red]);
+    let second = create_article_with_tags(&jwt, &[&red, &blue]);
+    let third = create_article_with_tags(&jwt, &[&blue, &green]);
+
+    let res = client.post(&format!("http://localhost:6767/api/articles/{}/favorite", first))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let slugs = |filters: String| -> Vec<String> {
+        get_articles(&format!("http://localhost:6767/api/articles?author={}&{}", author, filters))
+            .articles.into_iter().map(|article| article.slug).collect()
+    };
+
+    assert_eq!(slugs(format!("tag={}&tag={}", red, blue)), vec![third.clone(), second.clone(), first.clone()]);
+    assert_eq!(slugs(format!("tag={}&tag={}&tagMatch=all", red, blue)), vec![second.clone()]);
+    assert_eq!(slugs(format!("tag={}&excludeTag={}", blue, green)), vec![second.clone()]);
+    assert_eq!(slugs(format!("excludeTag={}", red)), vec![third.clone()]);
+    assert_eq!(slugs(format!("author=nobody-{}", unique)), vec![third.clone(), second.clone(), first.clone()]);
+    assert_eq!(slugs("sort=oldest".to_string()), vec![first.clone(), second.clone(), third.clone()]);
+    assert_eq!(slugs("sort=mostFavorited".to_string()), vec![first.clone(), third.clone(), second.clone()]);
+    assert_eq!(slugs("since=2000-01-01&until=2000-12-31".to_string()).len(), 0);
+
+    let oldest = get_articles(&format!("http://localhost:6767/api/articles?author={}&sort=oldest&limit=1", author));
+    let after = get_articles(&format!("http://localhost:6767/api/articles?author={}&sort=oldest&limit=1&after={}",
+        author, oldest.nextCursor.unwrap()));
+    assert_eq!(after.articles[0].slug, second);
+
+    let favorited = get_articles(&format!("http://localhost:6767/api/articles?author={}&sort=mostFavorited&limit=1", author));
+    assert_eq!(favorited.nextCursor, None);
+    let res = client.get(&format!("http://localhost:6767/api/articles?sort=mostFavorited&after={}", after.prevCursor.unwrap()))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+#[test]
+fn base_slug_test() {
+    let reserved = vec!["feed".to_string()];
+    assert_eq!(base_slug("Hello World", &reserved), ("hello-world".to_string(), false));
+    assert_eq!(base_slug("Feed", &reserved), ("feed".to_string(), true));
+    assert_eq!(base_slug("!!!", &reserved), ("article".to_string(), false));
+
+    let (slug, _) = base_slug(&"word ".repeat(100), &reserved);
+    assert!(slug.len() <= MAX_SLUG_BASE_LENGTH);
+    assert!(!slug.ends_with('-'));
+
+    assert_eq!(slug_suffix(&SlugStrategy::Counter), "");
+    let suffix = slug_suffix(&SlugStrategy::Random);
+    assert_eq!(suffix.len(), SLUG_SUFFIX_LENGTH);
+    assert!(suffix.bytes().all(|c| SLUG_SUFFIX_CHARACTERS.contains(&c)));
+}
+
+#[cfg(test)]
+fn create_article_titled(jwt: &str, title: &str) -> Article {
+    let client = Client::new();
+
+    let body = format!( r#"{{"article": {{"title": "{}","description": "Ever wonder how?","body": "Same title"}}}}"#, title);
+    let mut res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let create_result : CreateArticleResult = serde_json::from_str(&buffer).unwrap();   
+    create_result.article
+}
+
+#[cfg(test)]
+#[test]
+fn duplicate_title_slug_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let title = format!("Twice {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+
+    let first = create_article_titled(&jwt, &title);
+    let second = create_article_titled(&jwt, &title);
+    assert_eq!(first.slug, slugify(title.to_owned()));
+    assert!(second.slug.starts_with(&first.slug));
+    assert!(second.slug != first.slug);
+
+    let feed = create_article_titled(&jwt, "Feed");
+    assert!(feed.slug != "feed");
+    assert!(feed.slug.starts_with("feed-"));
+
+    // renaming onto a taken title keeps both articles reachable
+    let body = format!(r#"{{"article": {{"title": "{}"}}}}"#, title);
+    let mut res = client.put(&format!("http://localhost:6767/api/articles/{}", slug))
+        .header(Authorization(Bearer {token: jwt}))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let updated : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert!(updated.article.slug.starts_with(&first.slug));
+    assert!(updated.article.slug != first.slug && updated.article.slug != second.slug);
+}
+
+#[cfg(test)]
+#[test]
+fn renamed_article_redirect_test() {
+    let mut client = Client::new();
+    client.set_redirect_policy(hyper::client::RedirectPolicy::FollowNone);
+
+    let (jwt, slug, _) = login_create_article(false);
+    let title = format!("Renamed {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+    let body = format!(r#"{{"article": {{"title": "{}"}}}}"#, title);
+    let res = client.put(&format!("http://localhost:6767/api/articles/{}", slug))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let renamed = slugify(title);
+
+    let res = client.get(&format!("http://localhost:6767/api/articles/{}", slug))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::MovedPermanently);
+    assert_eq!(res.headers.get::<Location>(), Some(&Location(format!("/api/articles/{}", renamed))));
+
+    let mut res = client.post(&format!("http://localhost:6767/api/articles/{}/favorite", slug))
+        .header(Authorization(Bearer {token: jwt}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let favorited : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(favorited.article.slug, renamed);
+    assert_eq!(favorited.article.favoritesCount, 1);
+
+    let res = client.get(&format!("http://localhost:6767/api/articles/{}/comments", slug))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client.get("http://localhost:6767/api/articles/no-such-article-ever")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[cfg(test)]
+fn update_article_tags(jwt: &str, slug: &str, body: &str) -> (StatusCode, Vec<String>) {
+    let client = Client::new();
+
+    let mut res = client.put(&format!("http://localhost:6767/api/articles/{}", slug))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    if res.status != hyper::Ok {
+        return (res.status, Vec::new());
+    }
+    let updated : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    let mut tags = updated.article.tagList;
+    tags.sort();
+    (res.status, tags)
+}
+
+#[cfg(test)]
+#[test]
+fn update_article_tags_test() {
+    let (jwt, slug, _) = login_create_article(false);
+    let own_tag = format!("edited{}x{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+
+    let body = format!(r#"{{"article": {{"addTags": ["{}"], "removeTags": ["angularjs"]}}}}"#, own_tag);
+    let (status, tags) = update_article_tags(&jwt, &slug, &body);
+    assert_eq!(status, hyper::Ok);
+    assert_eq!(tags, vec!["dragons".to_string(), own_tag.clone(), "reactjs".to_string()]);
+    assert!(get_articles(&format!("http://localhost:6767/api/articles?tag={}", own_tag)).articles.iter().any(|a| a.slug == slug));
+
+    let (status, tags) = update_article_tags(&jwt, &slug, r#"{"article": {"tagList": ["dragons"]}}"#);
+    assert_eq!(status, hyper::Ok);
+    assert_eq!(tags, vec!["dragons".to_string()]);
+
+    // tag only updates leave the text alone
+    let mut res = Client::new().get(&format!("http://localhost:6767/api/articles/{}", slug)).send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let article : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(article.article.description, "Ever wonder how?");
+    assert_eq!(article.article.body, "You have to believe");
+
+    // the tag only this article had is gone
+    let mut res = Client::new().get(&format!("http://localhost:6767/api/tags?q={}", own_tag)).send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    assert!(!buffer.contains(&own_tag));
+
+    let (status, _) = update_article_tags(&jwt, &slug, r#"{"article": {"tagList": [], "addTags": ["dragons"]}}"#);
+    assert_eq!(status, StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+fn create_draft(jwt: &str) -> Article {
+    let client = Client::new();
+
+    let title = format!("Draft {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+    let body = format!(r#"{{"article": {{"title": "{}","description": "Not yet","body": "Work in progress",
+                "tagList": ["dragons"], "status": "draft"}}}}"#, title);
+    let mut res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let created : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    created.article
+}
+
+#[cfg(test)]
+#[test]
+fn draft_article_test() {
+    let client = Client::new();
+
+    let (jwt, _, user_name) = login_create_article(false);
+    let (other_jwt, _, _) = login_create_article(false);
+    let draft = create_draft(&jwt);
+    assert_eq!(draft.status, "draft");
+    assert_eq!(draft.publishedAt, None);
+
+    let url = format!("http://localhost:6767/api/articles/{}", draft.slug);
+    let res = client.get(&url).header(Authorization(Bearer {token: other_jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, StatusCode::NotFound);
+    let res = client.get(&url).header(Authorization(Bearer {token: jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let listed = get_articles(&format!("http://localhost:6767/api/articles?author={}&limit=100", user_name));
+    assert!(!listed.articles.iter().any(|article| article.slug == draft.slug));
+
+    let mut res = client.get("http://localhost:6767/api/articles?status=draft&limit=100")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let drafts : ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert!(drafts.articles.iter().any(|article| article.slug == draft.slug));
+    assert!(drafts.articles.iter().all(|article| article.status == "draft" && article.author.username == user_name));
+
+    let res = client.get("http://localhost:6767/api/articles?status=draft").send().unwrap();
+    assert_eq!(res.status, StatusCode::Unauthorized);
+
+    let mut res = client.post(&format!("{}/publish", url))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let published : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(published.article.status, "published");
+    assert!(published.article.publishedAt.is_some());
+
+    let listed = get_articles(&format!("http://localhost:6767/api/articles?author={}&limit=100", user_name));
+    assert!(listed.articles.iter().any(|article| article.slug == draft.slug));
+
+    let mut res = client.delete(&format!("{}/publish", url))
+        .header(Authorization(Bearer {token: jwt}))
+        .body("")
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let unpublished : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(unpublished.article.status, "draft");
+    assert_eq!(unpublished.article.publishedAt, None);
+}
+
+#[cfg(test)]
+fn create_scheduled(jwt: &str, publish_at: &str) -> (StatusCode, String) {
+    let client = Client::new();
+
+    let title = format!("Scheduled {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+    let body = format!(r#"{{"article": {{"title": "{}","description": "Soon","body": "Coming up",
+                "tagList": ["dragons"], "publishAt": "{}"}}}}"#, title, publish_at);
+    let mut res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    (res.status, buffer)
+}
+
+#[cfg(test)]
+#[test]
+fn scheduled_article_test() {
+    let client = Client::new();
+
+    let (jwt, _, user_name) = login_create_article(false);
+    let (other_jwt, _, _) = login_create_article(false);
+
+    let (status, _) = create_scheduled(&jwt, "2017-08-26T22:35:28Z");
+    assert_eq!(status, StatusCode::UnprocessableEntity);
+
+    let publish_at = chrono::Utc::now() + chrono::Duration::seconds(2);
+    let (status, buffer) = create_scheduled(&jwt, &publish_at.to_rfc3339());
+    assert_eq!(status, hyper::Ok);
+    let scheduled : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(scheduled.article.status, "scheduled");
+    assert_eq!(scheduled.article.publishedAt, None);
+    let pending = scheduled.article.publishAt.unwrap() - publish_at.naive_utc();
+    assert!(pending.num_seconds().abs() < 1);
+
+    let url = format!("http://localhost:6767/api/articles/{}", scheduled.article.slug);
+    let res = client.get(&url).header(Authorization(Bearer {token: other_jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, StatusCode::NotFound);
+    let listed = get_articles(&format!("http://localhost:6767/api/articles?author={}&limit=100", user_name));
+    assert!(!listed.articles.iter().any(|article| article.slug == scheduled.article.slug));
+
+    // visible once due, whether or not the scheduler ran yet
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    let res = client.get(&url).header(Authorization(Bearer {token: other_jwt})).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let listed = get_articles(&format!("http://localhost:6767/api/articles?author={}&limit=100", user_name));
+    let article = listed.articles.iter().find(|article| article.slug == scheduled.article.slug).unwrap();
+    assert_eq!(article.status, "published");
+}
+
+#[cfg(test)]
+#[test]
+fn article_status_test() {
+    assert_eq!(article_status(None, None), Ok((None, String::new())));
+    assert_eq!(article_status(Some("unlisted"), None), Ok((Some(ArticleStatus::Unlisted), String::new())));
+    assert!(article_status(Some("scheduled"), None).is_err());
+    assert!(article_status(Some("draft"), Some("2999-01-01")).is_err());
+    assert!(article_status(None, Some("2000-01-01")).is_err());
+    assert!(article_status(None, Some("tomorrow")).is_err());
+    assert_eq!(article_status(None, Some("2999-01-01T10:00:00+02:00")),
+        Ok((Some(ArticleStatus::Scheduled), "2999-01-01T08:00:00.000".to_string())));
+}
+
+#[cfg(test)]
+#[test]
+fn update_article_if_match_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+    let res = client.get(&url).send().unwrap();
+    let read = res.headers.get::<hyper::header::ETag>().unwrap().clone();
+
+    let res = client.put(&url)
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .header(hyper::header::IfMatch::Items(vec![read.0.clone()]))
+        .body(r#"{"article": {"body": "First tab"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let written = res.headers.get::<hyper::header::ETag>().unwrap().clone();
+    assert!(written != read);
+
+    let res = client.put(&url)
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .header(hyper::header::IfMatch::Items(vec![read.0]))
+        .body(r#"{"article": {"body": "Second tab"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::PreconditionFailed);
+    assert_eq!(res.headers.get::<hyper::header::ETag>(), Some(&written));
+
+    let mut res = client.get(&url).send().unwrap();
+    assert_eq!(res.headers.get::<hyper::header::ETag>(), Some(&written));
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let article : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(article.article.body, "First tab");
+
+    // without If-Match the last write wins, as before
+    let res = client.put(&url)
+        .header(Authorization(Bearer {token: jwt}))
+        .body(r#"{"article": {"body": "Third tab"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn conditional_get_article_test() {
+    use hyper::header::{CacheControl, CacheDirective, ETag, IfModifiedSince, IfNoneMatch, LastModified, Vary};
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let (other_jwt, _, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let tag = res.headers.get::<ETag>().unwrap().clone();
+    let last_modified = res.headers.get::<LastModified>().unwrap().clone();
+    assert_eq!(res.headers.get::<CacheControl>(), Some(&CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(60)])));
+    match res.headers.get::<Vary>() {
+        Some(&Vary::Items(ref items)) => assert!(items.iter().any(|item| item.as_ref() == "Authorization")),
+        other => panic!("unexpected Vary {:?}", other),
+    }
+
+    let res = client.get(&url).header(IfNoneMatch::Items(vec![tag.0.clone()])).send().unwrap();
+    assert_eq!(res.status, StatusCode::NotModified);
+    // favorites and follows don't move Last-Modified, so for per user representations only the ETag counts
+    let res = client.get(&url).header(IfModifiedSince(last_modified.0)).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    // and it is GMT, as labeled
+    let modified = chrono::DateTime::parse_from_rfc2822(&last_modified.0.to_string()).unwrap();
+    assert!(chrono::Utc::now().signed_duration_since(modified).num_minutes().abs() < 5);
+
+    // per user responses are not shared
+    let res = client.get(&url).header(Authorization(Bearer {token: jwt})).send().unwrap();
+    assert_eq!(res.headers.get::<CacheControl>(), Some(&CacheControl(vec![CacheDirective::Private, CacheDirective::NoCache])));
+
+    // a favorite does not touch the article row, but changes the representation
+    let res = client.post(&format!("{}/favorite", url))
+        .header(Authorization(Bearer {token: other_jwt}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let res = client.get(&url).header(IfNoneMatch::Items(vec![tag.0.clone()])).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    assert!(res.headers.get::<ETag>() != Some(&tag));
+}
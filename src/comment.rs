@@ -16,8 +16,6 @@ extern crate tiberius;
 
 extern crate toml;
 
-extern crate reroute;
-
 extern crate jwt;
 
 extern crate futures_state_stream;
@@ -26,7 +24,7 @@ extern crate slug;
 
 use chrono::prelude::*;
 
-use reroute::{Captures};
+use router::Params;
 
 use super::*;
 
@@ -54,43 +52,47 @@ fn get_simple_comment_from_row( row : tiberius::query::QueryRow ) -> Option<Comm
 }
 
 
+fn get_paged_comment_from_row( row : tiberius::query::QueryRow ) -> (Option<(i32, Comment)>, i32) {
+    let id : i32 = row.get(0);
+    (get_simple_comment_from_row(row).map(|comment| (id, comment)), 0)
+}
+
 fn get_comment_from_row( row : tiberius::query::QueryRow ) -> Option<CommentResult> {
     let result = Some(CommentResult{comment:get_simple_comment_from_row(row).unwrap()});
     result    
 }    
 
-pub fn add_comment_handler(req: Request, res: Response, c: Captures) {
+pub fn add_comment_handler(req: Request, res: Response, p: Params) {
     let (body, logged_id) = prepare_parameters(req);
 
     let add_comment : AddComment = serde_json::from_str(&body).unwrap(); 
     let comment_body : &str = &add_comment.comment.body;
     println!("comment_body: {}", comment_body);
     
-    let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "").replace("/comments", "");
+    let slug = p.get("slug");
     println!("add_comment_handler slug: '{}'", slug);
 
     process(
         res,
-        r#"declare @id int; select top 1 @id = id from Articles where Slug = @p1 ORDER BY 1; 
+        r#"declare @id int = dbo.VisibleArticleId(@p1, @P2);
           DECLARE @logged int = @P2;
-          insert into Comments (createdAt, body, ArticleId, Author ) values (getdate(), @p3, @id, @logged);
+          insert into Comments (createdAt, body, ArticleId, Author ) select GETUTCDATE(), @p3, @id, @logged WHERE @id IS NOT NULL;
           declare @commentid int = SCOPE_IDENTITY(); 
         "#, 
         COMMENT_SELECT,
         get_comment_from_row,
-        &[&(slug.as_str()), &logged_id, &comment_body, ]
+        &[&slug, &logged_id, &comment_body, ]
     );
 }
 
 
-pub fn delete_comment_handler(req: Request, res: Response, c: Captures) {
+pub fn delete_comment_handler(req: Request, res: Response, p: Params) {
     let (_, logged_id) = prepare_parameters(req);   
 
-    let caps = c.unwrap();
-    let url_params = &caps[0];
-    let id = url_params.split("/").last().unwrap();
-    println!("delete_comment_handler url_params: {}",url_params);
+    let id : i32 = match p.parse("id") {
+        Ok(id) => id,
+        Err(e) => return send_error(res, StatusCode::BadRequest, &e),
+    };
     println!("id: {}", id);
 
     process(
@@ -107,24 +109,43 @@ pub fn delete_comment_handler(req: Request, res: Response, c: Captures) {
 
 fn comments_result( _ : CommentsResult ) {}
 
-pub fn get_comments_handler(req: Request, res: Response, c: Captures) {    
+pub fn get_comments_handler(req: Request, res: Response, p: Params) {    
     let (_, logged_id) = prepare_parameters(req);   
 
-    let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "").replace("/comments", "");
+    let slug = p.get("slug");
     println!("get_comments_handler slug: '{}'", slug);
 
-    process_container(
+    // without any paging parameter all comments are returned, as before paging existed
+    let query = p.query();
+    let paged = ["limit", "offset", "after", "before"].iter().any(|name| query.get(name).is_some());
+    let pager = if paged {
+        match cursor::Pager::from_query(query, "comments") {
+            Ok(pager) => pager,
+            Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+        }
+    } else {
+        cursor::Pager::unlimited("comments")
+    };
+    let (offset, fetch, after, before) = (pager.offset(), pager.fetch(), pager.after(), pager.before());
+
+    process_paged_container(
         res,
-        r#"declare @id int; select top 1 @id = id from Articles where Slug = @p1 ORDER BY 1;
+        r#"declare @id int = dbo.VisibleArticleId(@p1, @p2);
         declare @logged int = @p2;
+        declare @after int = @p5;
+        declare @before int = @p6;
         "#,
         r#"select Comments.Id, createdAt, body,  Users.UserName, Users.Bio, Users.[Image],
         (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following]
-                from Comments inner join Users ON Users.Id = Comments.Author where ArticleId = @id"#,
-        get_simple_comment_from_row,
+                from Comments inner join Users ON Users.Id = Comments.Author where Comments.Id IN (
+                    SELECT Id FROM Comments WHERE ArticleId = @id AND (@after = 0 OR Id > @after) AND (@before = 0 OR Id < @before)
+                    order by CASE WHEN @before = 0 THEN Id ELSE -Id END OFFSET @p3 ROWS FETCH NEXT @p4 ROWS Only
+                )
+                order by Comments.Id"#,
+        get_paged_comment_from_row,
         comments_result,
-        &[&(slug.as_str()),&logged_id]
+        &pager,
+        &[&slug, &logged_id, &offset, &fetch, &after, &before]
     );
 }
 
@@ -0,0 +1,192 @@
+extern crate hyper;
+extern crate serde;
+extern crate serde_json;
+extern crate chrono;
+extern crate crypto;
+extern crate unicase;
+
+use chrono::prelude::*;
+
+use crypto::digest::Digest;
+use crypto::md5::Md5;
+
+use hyper::server::Response;
+use hyper::status::StatusCode;
+use hyper::header::{Headers, ETag, EntityTag, IfMatch, IfNoneMatch, IfModifiedSince, LastModified, HttpDate,
+    CacheControl, CacheDirective, Vary, ContentType};
+use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
+use unicase::UniCase;
+
+use super::*;
+
+/// How long caches may keep the responses of a route.
+pub struct CachePolicy {
+    pub max_age: u32,
+    /// The response depends on who asks, e.g. `favorited` and `following`: it varies on `Authorization`
+    /// and is private to the user when there is one.
+    pub per_user: bool,
+}
+
+pub static ARTICLE_CACHE : CachePolicy = CachePolicy { max_age: 60, per_user: true };
+pub static ARTICLE_LIST_CACHE : CachePolicy = CachePolicy { max_age: 10, per_user: true };
+pub static PROFILE_CACHE : CachePolicy = CachePolicy { max_age: 60, per_user: true };
+pub static TAGS_CACHE : CachePolicy = CachePolicy { max_age: 300, per_user: false };
+
+/// What a GET request already has, read before `prepare_parameters` consumes the request.
+pub struct Conditions {
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<NaiveDateTime>,
+    authorized: bool,
+}
+
+impl Conditions {
+    pub fn from_headers(headers: &Headers) -> Conditions {
+        Conditions {
+            if_none_match: headers.get::<IfNoneMatch>().cloned(),
+            if_modified_since: headers.get::<IfModifiedSince>().and_then(|since| parse_http_date(&since.0)),
+            authorized: headers.get_raw("Authorization").is_some(),
+        }
+    }
+
+    /// Whether the client's copy is still current; `If-Modified-Since` only counts without `If-None-Match`.
+    fn fresh(&self, tag: &EntityTag, last_modified: Option<NaiveDateTime>) -> bool {
+        match (&self.if_none_match, self.if_modified_since, last_modified) {
+            (&Some(IfNoneMatch::Any), _, _) => true,
+            (&Some(IfNoneMatch::Items(ref tags)), _, _) => tags.iter().any(|t| t.weak_eq(tag)),
+            (&None, Some(since), Some(modified)) => modified.with_nanosecond(0).unwrap() <= since,
+            _ => false,
+        }
+    }
+}
+
+fn parse_http_date(date: &HttpDate) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc2822(&date.to_string()).ok().map(|date| date.naive_utc())
+}
+
+fn http_date(date: NaiveDateTime) -> HttpDate {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string().parse().unwrap()
+}
+
+/// Strong `ETag` of a representation: the row version, when there is one, and a hash of the body.
+/// The row version alone changes with every write, it is what `If-Match` compares.
+fn body_tag(version: Option<i64>, body: &str) -> EntityTag {
+    let mut md5 = Md5::new();
+    md5.input_str(body);
+    match version {
+        Some(version) => EntityTag::strong(format!("{}-{}", version, md5.result_str())),
+        None => EntityTag::strong(md5.result_str()),
+    }
+}
+
+/// `If-Match` as SQL parameters: whether to check at all, and the comma separated versions the
+/// client has seen. A missing header and `*` check nothing, weak tags never match.
+pub fn if_match(headers: &Headers) -> (i32, String) {
+    match headers.get::<IfMatch>() {
+        Some(&IfMatch::Items(ref tags)) => {
+            let versions = tags.iter()
+                .filter(|tag| !tag.weak)
+                .filter_map(|tag| tag.tag().split('-').next().and_then(|version| version.parse::<i64>().ok()))
+                .map(|version| version.to_string())
+                .collect::<Vec<String>>();
+            (1, versions.join(","))
+        }
+        _ => (0, String::new()),
+    }
+}
+
+fn send_body(mut res: Response, tag: EntityTag, body: &str) {
+    res.headers_mut().set(ETag(tag));
+    res.headers_mut().set(
+        ContentType(Mime(TopLevel::Application, SubLevel::Json,
+                    vec![(Attr::Charset, Value::Utf8)]))
+    );
+    res.send(body.as_bytes()).unwrap();
+}
+
+pub fn send_versioned<T: serde::Serialize>(res: Response, version: i64, result: &T) {
+    let body = serde_json::to_string(result).unwrap();
+    send_body(res, body_tag(Some(version), &body), &body);
+}
+
+/// 412 for a write whose `If-Match` names an older version; the `ETag` of `current` tells the client what it missed.
+pub fn send_precondition_failed<T: serde::Serialize>(mut res: Response, version: i64, current: &T) {
+    let body = serde_json::to_string(current).unwrap();
+    res.headers_mut().set(ETag(body_tag(Some(version), &body)));
+    send_error(res, StatusCode::PreconditionFailed, "the resource was changed since it was read");
+}
+
+fn vary_on_authorization(headers: &mut Headers) {
+    let mut items = match headers.get::<Vary>() {
+        Some(&Vary::Any) => return,
+        Some(&Vary::Items(ref items)) => items.clone(),
+        None => Vec::new(),
+    };
+    items.push(UniCase("Authorization".to_owned()));
+    headers.set(Vary::Items(items));
+}
+
+/// Sends `result` with its validators and `Cache-Control`, or just 304 when the client's copy is current.
+/// `Last-Modified` (UTC) only covers the row itself while changes like a new favorite show in the `ETag`
+/// alone, so `If-Modified-Since` is not honored for per user representations.
+pub fn send_cached<T: serde::Serialize>(mut res: Response, conditions: &Conditions, policy: &CachePolicy,
+                                       version: Option<i64>, last_modified: Option<NaiveDateTime>, result: &T) {
+    let body = serde_json::to_string(result).unwrap();
+    let tag = body_tag(version, &body);
+
+    if policy.per_user {
+        vary_on_authorization(res.headers_mut());
+    }
+    let directives = if policy.per_user && conditions.authorized {
+        vec![CacheDirective::Private, CacheDirective::NoCache]
+    } else {
+        vec![CacheDirective::Public, CacheDirective::MaxAge(policy.max_age)]
+    };
+    res.headers_mut().set(CacheControl(directives));
+    if let Some(last_modified) = last_modified {
+        res.headers_mut().set(LastModified(http_date(last_modified)));
+    }
+
+    if conditions.fresh(&tag, if policy.per_user { None } else { last_modified }) {
+        res.headers_mut().set(ETag(tag));
+        *res.status_mut() = StatusCode::NotModified;
+        return;
+    }
+    send_body(res, tag, &body);
+}
+
+#[cfg(test)]
+#[test]
+fn if_match_test() {
+    let mut headers = Headers::new();
+    assert_eq!(if_match(&headers), (0, String::new()));
+
+    headers.set(IfMatch::Any);
+    assert_eq!(if_match(&headers), (0, String::new()));
+
+    headers.set(IfMatch::Items(vec![EntityTag::strong("42".to_owned()), EntityTag::weak("43".to_owned()),
+        EntityTag::strong("x".to_owned()), body_tag(Some(44), "{}")]));
+    assert_eq!(if_match(&headers), (1, "42,44".to_string()));
+
+    headers.set(IfMatch::Items(vec![EntityTag::weak("43".to_owned())]));
+    assert_eq!(if_match(&headers), (1, String::new()));
+}
+
+#[cfg(test)]
+#[test]
+fn fresh_test() {
+    let tag = body_tag(Some(7), "{}");
+    let modified = NaiveDate::from_ymd(2017, 8, 26).and_hms_milli(22, 35, 28, 500);
+
+    let mut headers = Headers::new();
+    assert!(!Conditions::from_headers(&headers).fresh(&tag, Some(modified)));
+
+    headers.set(IfModifiedSince(http_date(NaiveDate::from_ymd(2017, 8, 26).and_hms(22, 35, 28))));
+    assert!(Conditions::from_headers(&headers).fresh(&tag, Some(modified)));
+    assert!(!Conditions::from_headers(&headers).fresh(&tag, Some(modified + chrono::Duration::seconds(1))));
+
+    // If-None-Match wins over If-Modified-Since
+    headers.set(IfNoneMatch::Items(vec![body_tag(Some(7), "[]")]));
+    assert!(!Conditions::from_headers(&headers).fresh(&tag, Some(modified)));
+    headers.set(IfNoneMatch::Items(vec![EntityTag::weak(tag.tag().to_owned())]));
+    assert!(Conditions::from_headers(&headers).fresh(&tag, Some(modified)));
+}
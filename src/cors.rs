@@ -0,0 +1,99 @@
+extern crate hyper;
+extern crate unicase;
+
+use std::str;
+
+use hyper::server::{Handler, Request, Response};
+use hyper::status::StatusCode;
+use hyper::method::Method;
+use hyper::header::{Headers, AccessControlAllowOrigin, AccessControlAllowHeaders, AccessControlAllowMethods,
+    AccessControlAllowCredentials, AccessControlExposeHeaders, AccessControlMaxAge, Vary};
+use unicase::UniCase;
+
+use settings::CorsSettings;
+
+/// Sets the CORS headers on every response and answers preflight requests itself.
+pub struct Cors<H: Handler> {
+    handler: H,
+    any_origin: bool,
+    allowed_origins: Vec<String>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<UniCase<String>>,
+    allow_credentials: bool,
+    max_age: u32,
+}
+
+impl<H: Handler> Cors<H> {
+    pub fn new(handler: H, settings: &CorsSettings) -> Cors<H> {
+        Cors {
+            handler: handler,
+            any_origin: settings.allowed_origins.iter().any(|o| o == "*"),
+            allowed_origins: settings.allowed_origins.clone(),
+            allowed_methods: settings.allowed_methods.iter().filter_map(|m| m.parse::<Method>().ok()).collect(),
+            allowed_headers: settings.allowed_headers.iter().map(|h| UniCase(h.to_owned())).collect(),
+            allow_credentials: settings.allow_credentials,
+            max_age: settings.max_age,
+        }
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` for the request, `None` when the origin is not allowed.
+    fn allow_origin(&self, origin: Option<&str>) -> Option<AccessControlAllowOrigin> {
+        if self.any_origin {
+            return Some(AccessControlAllowOrigin::Any);
+        }
+        match origin {
+            Some(origin) if self.allowed_origins.iter().any(|o| o == origin) =>
+                Some(AccessControlAllowOrigin::Value(origin.to_owned())),
+            _ => None,
+        }
+    }
+
+    fn set_headers(&self, headers: &mut Headers, origin: Option<&str>) -> bool {
+        if !self.any_origin {
+            headers.set(Vary::Items(vec![UniCase("Origin".to_owned())]));
+        }
+        match self.allow_origin(origin) {
+            Some(allow_origin) => {
+                headers.set(allow_origin);
+                headers.set(AccessControlAllowHeaders(self.allowed_headers.clone()));
+                // scripts need the ETag to send it back in If-Match
+                headers.set(AccessControlExposeHeaders(vec![UniCase("ETag".to_owned())]));
+                if self.allow_credentials {
+                    headers.set(AccessControlAllowCredentials);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn raw_header<'a>(headers: &'a Headers, name: &str) -> Option<&'a str> {
+    headers.get_raw(name)
+        .and_then(|values| values.first())
+        .and_then(|value| str::from_utf8(value).ok())
+}
+
+impl<H: Handler> Handler for Cors<H> {
+    fn handle<'a, 'k>(&'a self, req: Request<'a, 'k>, mut res: Response<'a>) {
+        let allowed = {
+            let origin = raw_header(&req.headers, "Origin");
+            self.set_headers(res.headers_mut(), origin)
+        };
+
+        let preflight = req.method == Method::Options
+            && raw_header(&req.headers, "Access-Control-Request-Method").is_some();
+        if preflight {
+            if allowed {
+                res.headers_mut().set(AccessControlAllowMethods(self.allowed_methods.clone()));
+                res.headers_mut().set(AccessControlMaxAge(self.max_age));
+                *res.status_mut() = StatusCode::NoContent;
+            } else {
+                *res.status_mut() = StatusCode::Forbidden;
+            }
+            return;
+        }
+
+        self.handler.handle(req, res);
+    }
+}
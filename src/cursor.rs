@@ -0,0 +1,211 @@
+extern crate crypto;
+extern crate rand;
+
+use std::str;
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use rand::Rng;
+
+use query::Query;
+use SETTINGS;
+
+static SIGNATURE_LENGTH : usize = 16;
+
+lazy_static! {
+    static ref CURSOR_KEY : Vec<u8> = match SETTINGS.pagination.cursor_secret {
+        Some(ref secret) => secret.as_bytes().to_vec(),
+        None => rand::thread_rng().gen_iter::<u8>().take(32).collect(),
+    };
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+fn signature(key: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    hmac.input(payload);
+    hmac.result().code()[..SIGNATURE_LENGTH].to_vec()
+}
+
+/// `scope` keeps a cursor of one listing (e.g. comments) from being replayed on another.
+fn encode_with(key: &[u8], scope: &str, id: i32) -> String {
+    let payload = format!("{}:{}", scope, id);
+    format!("{}.{}", to_hex(payload.as_bytes()), to_hex(&signature(key, payload.as_bytes())))
+}
+
+fn decode_with(key: &[u8], scope: &str, cursor: &str) -> Option<i32> {
+    let mut parts = cursor.splitn(2, '.');
+    let payload = parts.next().and_then(from_hex)?;
+    let given = parts.next().and_then(from_hex)?;
+    if !fixed_time_eq(&given, &signature(key, &payload)) {
+        return None;
+    }
+    let payload = str::from_utf8(&payload).ok()?;
+    let mut parts = payload.splitn(2, ':');
+    if parts.next() != Some(scope) {
+        return None;
+    }
+    parts.next().and_then(|id| id.parse::<i32>().ok()).filter(|id| *id > 0)
+}
+
+/// Where a page starts: the classic `offset`, or just past the item a cursor points at.
+#[derive(Debug, PartialEq)]
+pub enum Position {
+    Offset(i32),
+    After(i32),
+    Before(i32),
+}
+
+/// One page of a listing ordered by Id, with the cursors to the neighbouring pages.
+pub struct Window<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+/// Paging of one listing. The SQL gets `offset()`, `fetch()`, `after()` and `before()` (0 when unused)
+/// and returns the rows in listing order; in `Before` mode it picks the `fetch()` rows nearest to the cursor.
+#[derive(Debug)]
+pub struct Pager {
+    key: &'static [u8],
+    scope: &'static str,
+    /// Cursors only make sense when the listing is ordered by Id.
+    cursors: bool,
+    pub position: Position,
+    pub limit: i32,
+}
+
+impl Pager {
+    /// Reads `limit` with either `offset` or one of `after`/`before`; the error is meant for a 422 response.
+    pub fn from_query(query: &Query, scope: &'static str) -> Result<Pager, String> {
+        let page = query.page()?;
+        let decode_param = |name: &str, cursor: &str| decode_with(&CURSOR_KEY, scope, cursor)
+            .ok_or_else(|| format!("{} is not a valid cursor", name));
+        let position = match (query.get("after"), query.get("before")) {
+            (Some(_), Some(_)) => return Err("after and before can not be combined".to_string()),
+            (Some(cursor), None) => Position::After(decode_param("after", cursor)?),
+            (None, Some(cursor)) => Position::Before(decode_param("before", cursor)?),
+            (None, None) => Position::Offset(page.offset),
+        };
+        if position != Position::Offset(page.offset) && query.get("offset").is_some() {
+            return Err("offset can not be combined with after or before".to_string());
+        }
+        Ok(Pager { key: &CURSOR_KEY, scope: scope, cursors: true, position: position, limit: page.limit })
+    }
+
+    /// Reads `limit` and `offset` for listings in an order cursors can not follow.
+    pub fn offset_only(query: &Query, scope: &'static str) -> Result<Pager, String> {
+        if query.get("after").is_some() || query.get("before").is_some() {
+            return Err("after and before can not be used with this sort, use offset".to_string());
+        }
+        let page = query.page()?;
+        Ok(Pager { key: &CURSOR_KEY, scope: scope, cursors: false, position: Position::Offset(page.offset), limit: page.limit })
+    }
+
+    /// The whole listing as one page, for listings clients never had to page through.
+    pub fn unlimited(scope: &'static str) -> Pager {
+        Pager { key: &CURSOR_KEY, scope: scope, cursors: false, position: Position::Offset(0), limit: ::std::i32::MAX - 1 }
+    }
+
+    pub fn offset(&self) -> i32 {
+        match self.position { Position::Offset(offset) => offset, _ => 0 }
+    }
+
+    pub fn after(&self) -> i32 {
+        match self.position { Position::After(id) => id, _ => 0 }
+    }
+
+    pub fn before(&self) -> i32 {
+        match self.position { Position::Before(id) => id, _ => 0 }
+    }
+
+    /// One row more than the limit tells whether there is another page.
+    pub fn fetch(&self) -> i32 {
+        self.limit + 1
+    }
+
+    /// Trims the fetched `(id, item)` rows to the page and works out the neighbouring cursors.
+    pub fn window<T>(&self, mut rows: Vec<(i32, T)>) -> Window<T> {
+        let extra = rows.len() > self.limit as usize;
+        let (has_next, has_prev) = match self.position {
+            Position::Offset(offset) => {
+                rows.truncate(self.limit as usize);
+                (extra, offset > 0)
+            }
+            Position::After(_) => {
+                rows.truncate(self.limit as usize);
+                (extra, true)
+            }
+            Position::Before(_) => {
+                if extra {
+                    rows.remove(0);
+                }
+                (true, extra)
+            }
+        };
+
+        let cursor = |&(id, _): &(i32, T)| encode_with(self.key, self.scope, id);
+        let (has_next, has_prev) = (has_next && self.cursors, has_prev && self.cursors);
+        let next_cursor = if has_next { rows.last().map(&cursor) } else { None };
+        let prev_cursor = if has_prev { rows.first().map(&cursor) } else { None };
+        Window {
+            items: rows.into_iter().map(|(_, item)| item).collect(),
+            next_cursor: next_cursor,
+            prev_cursor: prev_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cursor_signature_test() {
+    let cursor = encode_with(b"first key", "articles", 42);
+    assert_eq!(decode_with(b"first key", "articles", &cursor), Some(42));
+    assert_eq!(decode_with(b"other key", "articles", &cursor), None);
+    assert_eq!(decode_with(b"first key", "comments", &cursor), None);
+
+    let forged = format!("{}{}", to_hex(b"articles:41"), &cursor[cursor.find('.').unwrap()..]);
+    assert_eq!(decode_with(b"first key", "articles", &forged), None);
+    assert_eq!(decode_with(b"first key", "articles", "not a cursor"), None);
+    assert_eq!(decode_with(b"first key", "articles", ""), None);
+}
+
+#[cfg(test)]
+#[test]
+fn window_test() {
+    let rows = |ids: &[i32]| ids.iter().map(|id| (*id, *id)).collect::<Vec<(i32, i32)>>();
+    let pager = |position| Pager { key: b"test key", scope: "test", cursors: true, position: position, limit: 2 };
+    let decode = |cursor: String| decode_with(b"test key", "test", &cursor);
+
+    let window = pager(Position::Offset(0)).window(rows(&[9, 8, 7]));
+    assert_eq!(window.items, vec![9, 8]);
+    assert_eq!(window.next_cursor.map(&decode), Some(Some(8)));
+    assert_eq!(window.prev_cursor, None);
+
+    let window = pager(Position::After(8)).window(rows(&[7, 6]));
+    assert_eq!(window.items, vec![7, 6]);
+    assert_eq!(window.next_cursor, None);
+    assert_eq!(window.prev_cursor.map(&decode), Some(Some(7)));
+
+    let window = pager(Position::Before(7)).window(rows(&[10, 9, 8]));
+    assert_eq!(window.items, vec![9, 8]);
+    assert_eq!(window.next_cursor.map(&decode), Some(Some(8)));
+    assert_eq!(window.prev_cursor.map(&decode), Some(Some(9)));
+
+    let offset_only = Pager { key: b"test key", scope: "test", cursors: false, position: Position::Offset(2), limit: 2 };
+    let window = offset_only.window(rows(&[7, 6, 5]));
+    assert_eq!(window.items, vec![7, 6]);
+    assert_eq!(window.next_cursor, None);
+    assert_eq!(window.prev_cursor, None);
+}
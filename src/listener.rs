@@ -0,0 +1,99 @@
+extern crate hyper;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::net::{SocketAddr, Shutdown, IpAddr, Ipv4Addr};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+use hyper::net::{NetworkListener, NetworkStream};
+
+/// Unix domain sockets have no IP peer, hyper still wants one for `Request::remote_addr`.
+fn unspecified_addr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0)
+}
+
+pub struct UnixHttpListener(UnixListener);
+
+impl UnixHttpListener {
+    /// Binds to `path`, replacing a socket file left behind by a previous run.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixHttpListener> {
+        let path = path.as_ref();
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(UnixHttpListener(UnixListener::bind(path)?))
+    }
+}
+
+impl Clone for UnixHttpListener {
+    fn clone(&self) -> UnixHttpListener {
+        UnixHttpListener(self.0.try_clone().unwrap())
+    }
+}
+
+impl NetworkListener for UnixHttpListener {
+    type Stream = UnixHttpStream;
+
+    fn accept(&mut self) -> hyper::Result<UnixHttpStream> {
+        let (stream, _) = self.0.accept()?;
+        Ok(UnixHttpStream(stream))
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok(unspecified_addr())
+    }
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) {}
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) {}
+}
+
+pub struct UnixHttpStream(UnixStream);
+
+impl Clone for UnixHttpStream {
+    fn clone(&self) -> UnixHttpStream {
+        UnixHttpStream(self.0.try_clone().unwrap())
+    }
+}
+
+impl Read for UnixHttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for UnixHttpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl NetworkStream for UnixHttpStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok(unspecified_addr())
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.0.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.0.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        match self.0.shutdown(how) {
+            Ok(_) => Ok(()),
+            // the peer may already be gone, same as hyper's HttpStream
+            Err(ref e) if e.kind() == io::ErrorKind::NotConnected => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -22,8 +22,6 @@ extern crate toml;
 #[macro_use]
 extern crate lazy_static;
 
-extern crate reroute;
-
 extern crate jwt;
 
 extern crate futures_state_stream;
@@ -34,6 +32,17 @@ extern crate rand;
 
 extern crate unicase;
 
+extern crate url;
+
+#[cfg(feature = "tls")]
+extern crate openssl;
+
+#[cfg(unix)]
+extern crate signal_hook;
+
+#[cfg(windows)]
+extern crate ctrlc;
+
 use futures::Future;
 use tokio_core::reactor::Core;
 use tiberius::{SqlConnection};
@@ -41,18 +50,13 @@ use tiberius::stmt::ResultStreamExt;
 
 use chrono::prelude::*;
 
-use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
-use std::env;
-use std::path::PathBuf;
 
 use hyper::server::{Server, Request, Response};
-use reroute::{RouterBuilder, Captures};
 use hyper::status::StatusCode;
-use hyper::header::{AccessControlAllowOrigin, AccessControlAllowHeaders};
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH, Duration};
 
 pub fn since_the_epoch() -> u64 {
     let start = SystemTime::now();
@@ -63,8 +67,10 @@ pub fn since_the_epoch() -> u64 {
 #[cfg(test)]
 use hyper::Client;
 
-trait Container<T> {
-    fn create_new_with_items(Vec<T>) -> Self;
+/// A page of items together with the total number of items matching the request
+/// and the cursors of the neighbouring pages.
+trait PagedContainer<T> {
+    fn create_page(Vec<T>, i32, Option<String>, Option<String>) -> Self;
 }
 
 #[derive(Serialize, Deserialize)]
@@ -88,21 +94,44 @@ struct Article {
     tagList: Vec<String>,
     createdAt: NaiveDateTime,
     updatedAt: Option<NaiveDateTime>,
+    /// `draft`, `published`, `unlisted` or `scheduled`.
+    status: String,
+    /// When the article went live, in UTC; null until then.
+    publishedAt: Option<NaiveDateTime>,
+    /// When a `scheduled` article goes live, in UTC.
+    publishAt: Option<NaiveDateTime>,
     favorited : bool,
     favoritesCount : i32,
-    author : Profile
+    author : Profile,
+    /// Only in the feed.
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    source : Option<FeedSource>
+}
+
+/// Why an article is in the personal feed.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct FeedSource {
+    followedAuthor : bool,
+    followedTags : Vec<String>
 }
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 #[allow(non_snake_case)]
 struct ArticlesResult {
-    articles: Vec<Article>
+    articles: Vec<Article>,
+    articlesCount: i32,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    nextCursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    prevCursor: Option<String>
 }
 
-impl Container<Article> for ArticlesResult {
-    fn create_new_with_items( articles: Vec<Article> ) -> ArticlesResult {
-        ArticlesResult{articles:articles}
+impl PagedContainer<Article> for ArticlesResult {
+    fn create_page( articles: Vec<Article>, count: i32, next_cursor: Option<String>, prev_cursor: Option<String> ) -> ArticlesResult {
+        ArticlesResult{articles:articles, articlesCount:count, nextCursor:next_cursor, prevCursor:prev_cursor}
     }
 } 
 
@@ -114,10 +143,18 @@ struct UpdateArticle {
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
+#[allow(non_snake_case)]
 struct UpdateArticleDetail {
     title: Option<String>,
     description : Option<String>,
-    body : Option<String>
+    body : Option<String>,
+    status : Option<String>,
+    /// When a `scheduled` article goes live, in UTC.
+    publishAt : Option<String>,
+    /// Replaces all tags; `addTags` and `removeTags` change just the given ones.
+    tagList: Option<Vec<String>>,
+    addTags: Option<Vec<String>>,
+    removeTags: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -135,6 +172,12 @@ struct ProfileResult {
     profile: Profile,
 }
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct ProfilesResult {
+    profiles: Vec<Profile>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 #[allow(non_snake_case)]
@@ -158,11 +201,15 @@ struct CommentResult {
 #[allow(non_snake_case)]
 struct CommentsResult {
     comments: Vec<Comment>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    nextCursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    prevCursor: Option<String>
 }
 
-impl Container<Comment> for CommentsResult {
-    fn create_new_with_items( comments: Vec<Comment> ) -> CommentsResult {
-        CommentsResult{comments:comments}
+impl PagedContainer<Comment> for CommentsResult {
+    fn create_page( comments: Vec<Comment>, _: i32, next_cursor: Option<String>, prev_cursor: Option<String> ) -> CommentsResult {
+        CommentsResult{comments:comments, nextCursor:next_cursor, prevCursor:prev_cursor}
     }
 } 
 
@@ -208,18 +255,6 @@ struct Login {
     user : LoginDetails
 }
 
-#[derive(Debug, Deserialize)]
-struct Config {
-    database: Option<DatabaseConfig>,
-}  
-
-#[derive(Debug, Deserialize)]
-struct DatabaseConfig {
-    connection_string: Option<String>,
-    database_name: Option<String>,
-    create_database_secret: Option<String>,
-}
-
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 struct UpdateUser {
@@ -259,6 +294,10 @@ struct CreateArticleDetail {
     description: String,
     body: String,
     tagList: Option<Vec<String>>,
+    /// `published` when missing, `scheduled` when `publishAt` is given.
+    status: Option<String>,
+    /// When a `scheduled` article goes live, in UTC.
+    publishAt: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -273,63 +312,26 @@ struct UpdateUserDetail {
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
+#[allow(non_snake_case)]
+struct TagCount {
+    tag: String,
+    articlesCount: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
 struct GetTagsResult {
     tags: Vec<String>,
+    /// Only for the popular tags, in the order of `tags`.
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    tagCounts: Option<Vec<TagCount>>,
 }
 
-static CONFIG_FILE_NAME : &'static str = r#"conduit.toml"#;
+mod settings;
 
 lazy_static! {
-    pub static ref CONNECTION_STRING : String = match get_database_config().connection_string {
-            Some(cnn) => cnn,
-            None => panic!("connection string not present in [database] section in {}", CONFIG_FILE_NAME),
-        };
-    pub static ref DATABASE_NAME : String = match get_database_config().database_name {
-            Some(db_name) => db_name,
-            None => panic!("database name not present in [database] section in {}", CONFIG_FILE_NAME),
-        };  
-    pub static ref CREATE_DATABASE_SECRET : String = match get_database_config().create_database_secret {
-            Some(db_name) => db_name,
-            None => panic!("create database secret not present in [database] section in {}", CONFIG_FILE_NAME),
-        };  
-}
-
-fn get_database_config() -> DatabaseConfig {
-
-    let env_config = 
-        match env::var("DATABASECONFIG") {
-            Ok(lang) => lang,
-            Err(_) => "".to_string(),
-        };
-    let mut content = env_config.replace( "&&&", "\n");
-
-    let mut path = PathBuf::from(env::current_dir().unwrap());
-    path.push(CONFIG_FILE_NAME);
-    let display = path.display();
-
-    if path.exists() {
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("couldn't open {}: {}", display,
-                                                    why.description()),
-            Ok(file) => file,
-        };
-
-        match file.read_to_string(&mut content) {
-            Err(why) => panic!("couldn't read {}: {}", display,
-                                                    why.description()),
-            Ok(_) => print!("{} contains:\n{}", display, content),
-        }
-    }
-
-    let toml_str : &str = &content;
-    let config: Config = toml::from_str(toml_str).unwrap();
-
-    let database_config : DatabaseConfig = match config.database {
-        Some(database_config) => database_config,
-        None => panic!("database not present in {}", CONFIG_FILE_NAME),
-    };
-
-    database_config
+    pub static ref SETTINGS : settings::Settings = settings::load_or_exit();
 }
 
 use hyper::header::{Authorization, Bearer};
@@ -352,22 +354,62 @@ fn prepare_parameters( mut req: Request ) -> (String, i32) {
     (body, logged_id)
 }
 
-use unicase::UniCase;
 use hyper::header::{ContentType};
 use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
 
+fn send_json<T: serde::Serialize>(mut res: Response, result: &T) {
+    res.headers_mut().set(
+        ContentType(Mime(TopLevel::Application, SubLevel::Json,
+                    vec![(Attr::Charset, Value::Utf8)]))
+    );
+    let result = serde_json::to_string(result).unwrap();
+    let result : &[u8] = result.as_bytes();
+    res.send(&result).unwrap();
+}
+
+/// Makes `value` match literally inside a `LIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value.replace("[", "[[]").replace("%", "[%]").replace("_", "[_]")
+}
+
+fn send_error(mut res: Response, status: StatusCode, message: &str) {
+    *res.status_mut() = status;
+    res.headers_mut().set(
+        ContentType(Mime(TopLevel::Application, SubLevel::Json,
+                    vec![(Attr::Charset, Value::Utf8)]))
+    );
+
+    let result = InternalError{ errors: ErrorDetail{ body: vec![message.to_string()] } };
+    let result = serde_json::to_string(&result).unwrap();
+    let result : &[u8] = result.as_bytes();
+    res.send(&result).unwrap();
+}
+
 fn process<'a, T>(
-        mut res: Response, 
+        res: Response, 
         sql_command : &'static str,
         sql_select_command : &'static str,
         get_t_from_row : fn(tiberius::query::QueryRow) -> Option<T>,
         sql_params : &'a[&'a tiberius::ty::ToSql],
     ) where T: serde::Serialize
+    {
+        process_with(res, sql_command, sql_select_command, get_t_from_row, sql_params, |_| {});
+}
+
+/// Like `process`, but `before_send` sees the result before the client does.
+fn process_with<'a, T, F>(
+        mut res: Response, 
+        sql_command : &'static str,
+        sql_select_command : &'static str,
+        get_t_from_row : fn(tiberius::query::QueryRow) -> Option<T>,
+        sql_params : &'a[&'a tiberius::ty::ToSql],
+        before_send : F,
+    ) where T: serde::Serialize, F: FnOnce(&T)
     {
         let mut result : Option<T> = None; 
         {
             let mut sql = Core::new().unwrap();
-            let get_cmd = SqlConnection::connect(sql.handle(), CONNECTION_STRING.as_str() )
+            let get_cmd = SqlConnection::connect(sql.handle(), SETTINGS.database.connection_string.as_str() )
                 .and_then(|conn| conn.query(                            
                     format!("{};{}",sql_command, sql_select_command)
                     , sql_params
@@ -379,12 +421,6 @@ fn process<'a, T>(
             sql.run(get_cmd).unwrap(); 
         }
 
-        res.headers_mut().set(
-            AccessControlAllowOrigin::Any
-        );
-        res.headers_mut().set(
-            AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
-        );
         res.headers_mut().set(
             ContentType(Mime(TopLevel::Application, SubLevel::Json,
                         vec![(Attr::Charset, Value::Utf8)]))
@@ -392,24 +428,24 @@ fn process<'a, T>(
 
         if result.is_some() {
             let result = result.unwrap();
+            before_send(&result);
             let result = serde_json::to_string(&result).unwrap();
             let result : &[u8] = result.as_bytes();
             res.send(&result).unwrap();                       
         }   
 }
 
-fn process_container<'a, T, U>(
-        mut res: Response, 
+/// Runs a query and collects the rows, for handlers that combine them with data from elsewhere.
+fn fetch_rows<'a, T>(
         sql_command : &'static str,
         sql_select_command : &'static str,
         get_t_from_row : fn(tiberius::query::QueryRow) -> Option<T>,
-        _fix_u: fn(result:U),
         sql_params : &'a[&'a tiberius::ty::ToSql],
-    ) where T: serde::Serialize, U : Container<T>, U: serde::Serialize {
+    ) -> Vec<T> {
     let mut items : Vec<T>  = Vec::new();
     {
         let mut sql = Core::new().unwrap();
-        let get_cmd = SqlConnection::connect(sql.handle(), CONNECTION_STRING.as_str() )
+        let get_cmd = SqlConnection::connect(sql.handle(), SETTINGS.database.connection_string.as_str() )
             .and_then(|conn| conn.query(                            
                 format!("{};{}",sql_command, sql_select_command)
                 , sql_params
@@ -421,22 +457,53 @@ fn process_container<'a, T, U>(
         );
         sql.run(get_cmd).unwrap(); 
     }
+    items
+}
 
-    res.headers_mut().set(
-        AccessControlAllowOrigin::Any
-    );
-    res.headers_mut().set(
-        AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
-    );    
-    res.headers_mut().set(
-        ContentType(Mime(TopLevel::Application, SubLevel::Json,
-                    vec![(Attr::Charset, Value::Utf8)]))
-    );            
-    
-    let result = U::create_new_with_items(items);
-    let result = serde_json::to_string(&result).unwrap();
-    let result : &[u8] = result.as_bytes();
-    res.send(&result).unwrap();                       
+/// Runs a paged listing; every row carries the item's Id and the total count, a row without an item
+/// (e.g. the page is past the end) still reports the count.
+fn process_paged_container<'a, T, U>(
+        res: Response, 
+        sql_command : &'static str,
+        sql_select_command : &'static str,
+        get_t_from_row : fn(tiberius::query::QueryRow) -> (Option<(i32, T)>, i32),
+        fix_u: fn(result:U),
+        pager : &cursor::Pager,
+        sql_params : &'a[&'a tiberius::ty::ToSql],
+    ) where T: serde::Serialize, U : PagedContainer<T>, U: serde::Serialize {
+    let result = fetch_paged_container(sql_command, sql_select_command, get_t_from_row, fix_u, pager, sql_params);
+    send_json(res, &result);
+}
+
+/// Like `process_paged_container`, but returns the page instead of sending it.
+fn fetch_paged_container<'a, T, U>(
+        sql_command : &'static str,
+        sql_select_command : &'static str,
+        get_t_from_row : fn(tiberius::query::QueryRow) -> (Option<(i32, T)>, i32),
+        _fix_u: fn(result:U),
+        pager : &cursor::Pager,
+        sql_params : &'a[&'a tiberius::ty::ToSql],
+    ) -> U where T: serde::Serialize, U : PagedContainer<T>, U: serde::Serialize {
+    let mut items : Vec<(i32, T)>  = Vec::new();
+    let mut count : i32 = 0;
+    {
+        let mut sql = Core::new().unwrap();
+        let get_cmd = SqlConnection::connect(sql.handle(), SETTINGS.database.connection_string.as_str() )
+            .and_then(|conn| conn.query(                            
+                format!("{};{}",sql_command, sql_select_command)
+                , sql_params
+            ).for_each_row(|row| {
+                let (item, total) = get_t_from_row(row);
+                count = total;
+                if item.is_some() { items.push(item.unwrap()); }
+                Ok(())
+            })
+        );
+        sql.run(get_cmd).unwrap(); 
+    }
+
+    let window = pager.window(items);
+    U::create_page(window.items, count, window.next_cursor, window.prev_cursor)
 }
 
 mod user;
@@ -448,6 +515,33 @@ use article::*;
 mod comment;
 use comment::*;
 
+mod shutdown;
+
+#[cfg(unix)]
+mod listener;
+
+#[cfg(feature = "tls")]
+mod tls;
+
+mod cors;
+
+mod query;
+
+mod cursor;
+
+mod conditional;
+
+mod search;
+
+mod tag;
+
+mod revision;
+
+mod scheduler;
+
+mod router;
+use router::{RouterBuilder, Params};
+
 fn handle_row_no_value(_: tiberius::query::QueryRow) -> tiberius::TdsResult<()> {
     Ok(())
 }
@@ -470,25 +564,85 @@ fn get_tags_test() {
     assert_eq!(res.status, hyper::Ok);
 }
 
+#[cfg(test)]
+#[test]
+fn popular_tags_test() {
+    let client = Client::new();
+    login_create_article(false);
 
-fn test_handler(_: Request, res: Response, _: Captures) {
+    let mut res = client.get("http://localhost:6767/api/tags?limit=3&days=1")
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    assert_eq!(res.status, hyper::Ok);
+
+    let tags : GetTagsResult = serde_json::from_str(&buffer).unwrap();
+    let counts = tags.tagCounts.unwrap();
+    assert!(tags.tags.len() <= 3 && tags.tags.len() == counts.len());
+    assert!(counts.iter().all(|count| count.articlesCount > 0));
+    assert!(counts.windows(2).all(|pair| pair[0].articlesCount >= pair[1].articlesCount));
+
+    let res = client.get("http://localhost:6767/api/tags?days=-1")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+#[test]
+fn suggest_tags_test() {
+    let client = Client::new();
+    let (jwt, _, _) = login_create_article(false);
+
+    let mut res = client.get("http://localhost:6767/api/tags?q=REACT&limit=5")
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    assert_eq!(res.status, hyper::Ok);
+
+    let tags : GetTagsResult = serde_json::from_str(&buffer).unwrap();
+    assert!(tags.tags.len() <= 5);
+    assert!(tags.tags.iter().any(|tag| tag == "reactjs"));
+
+    // % and _ are literal: the decoy only matches the unescaped pattern, and starts with another letter
+    // so it doesn't come back as a similar sounding tag either
+    let unique : String = since_the_epoch().to_string().chars()
+        .map(|digit| (b'a' + digit.to_digit(10).unwrap() as u8) as char).collect();
+    let wildcards = format!("x{}%_y", unique);
+    let decoy = format!("kx{}aby", unique);
+    create_article_with_tags(&jwt, &[&wildcards, &decoy]);
+
+    let mut res = client.get(&format!("http://localhost:6767/api/tags?q=x{}%25_y", unique))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let tags : GetTagsResult = serde_json::from_str(&buffer).unwrap();
+    assert!(tags.tags.iter().any(|tag| *tag == wildcards));
+    assert!(!tags.tags.iter().any(|tag| *tag == decoy));
+}
+
+
+fn test_handler(_: Request, res: Response, _: Params) {
     res.send(b"Test works.").unwrap();
 }
 
-fn hello_handler(_: Request, res: Response, _: Captures) {
+fn hello_handler(_: Request, res: Response, _: Params) {
     res.send(b"Hello from Rust application in Hyper running in Azure IIS.").unwrap();
 }
 
-fn create_db_handler(mut req: Request, mut res: Response, _: Captures) {
+fn create_db_handler(mut req: Request, mut res: Response, _: Params) {
     let mut body = String::new();
     let _ = req.read_to_string(&mut body);    
-    if body == CREATE_DATABASE_SECRET.as_str() {
+    if SETTINGS.database.create_database_secret.as_ref().map_or(false, |secret| body == *secret) {
         let mut script = String::new();
         let mut f = File::open("database.sql").expect("Unable to open file");
         f.read_to_string(&mut script).expect("Unable to read string");
 
         let mut lp = Core::new().unwrap();
-        let future = SqlConnection::connect(lp.handle(), CONNECTION_STRING.as_str())
+        let future = SqlConnection::connect(lp.handle(), SETTINGS.database.connection_string.as_str())
         .and_then(|conn| {
             conn.query( script , &[ ]  ).for_each_row( handle_row_no_value )
         } );
@@ -498,90 +652,198 @@ fn create_db_handler(mut req: Request, mut res: Response, _: Captures) {
         *res.status_mut() = StatusCode::Unauthorized;        
     }
 }
-fn options_handler(_: Request, mut res: Response, _: Captures) {
-    res.headers_mut().set(
-        AccessControlAllowOrigin::Any
-    );    
-    res.headers_mut().set(
-        AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
-    );    
-    res.headers_mut().set(
-        ContentType(Mime(TopLevel::Application, SubLevel::Json,
-                     vec![(Attr::Charset, Value::Utf8)]))
-    );    
+fn get_tag_from_row( row : tiberius::query::QueryRow ) -> Option<String> {
+    let tag : &str = row.get(0);
+    Some(tag.to_string())
+}
+
+/// Tags for a typeahead: exact match first, then prefixes, then substrings, then ones sounding alike.
+fn suggest_tags(res: Response, conditions: &conditional::Conditions, q: &str, limit: i32) {
+    let pattern = escape_like(q);
+    let tags = fetch_rows(
+        r#"DECLARE @q nvarchar(max) = @P1; DECLARE @pattern nvarchar(max) = @P2; DECLARE @limit int = @P3;"#,
+        r#"SELECT TOP (@limit) Tag FROM (
+            SELECT Tag, CASE WHEN Tag = @q THEN 0 WHEN Tag LIKE @pattern + '%' THEN 1 WHEN Tag LIKE '%' + @pattern + '%' THEN 2 ELSE 3 END AS [Rank]
+            FROM [dbo].[Tags]
+        ) AS Ranked
+        WHERE [Rank] < 3 OR ( LEN(@q) >= 3 AND DIFFERENCE(Tag, @q) = 4 )
+        ORDER BY [Rank], LEN(Tag), Tag"#,
+        get_tag_from_row,
+        &[&q, &pattern.as_str(), &limit]
+    );
+    conditional::send_cached(res, conditions, &conditional::TAGS_CACHE, None, None, &GetTagsResult{ tags: tags, tagCounts: None });
 }
 
-fn get_tags_handler(_: Request, mut res: Response, _: Captures) {
-    let mut result : Option<GetTagsResult> = None; 
+fn get_tag_count_from_row( row : tiberius::query::QueryRow ) -> Option<TagCount> {
+    let tag : &str = row.get(0);
+    Some(TagCount{ tag: tag.to_string(), articlesCount: row.get(1) })
+}
 
-    {
-        let mut sql = Core::new().unwrap();
-        let get_tags_cmd = SqlConnection::connect(sql.handle(), CONNECTION_STRING.as_str() )
-            .and_then(|conn| conn.query(                            
-                "SELECT STRING_AGG(Tag, ',') FROM [dbo].[Tags]", &[]
-            ).for_each_row(|row| {
-                let all_tags : &str = row.get(0);
-                result = Some(GetTagsResult{ 
-                    tags: all_tags.split(",").map(|q| q.to_string()).collect()
-                });
-                Ok(())
-            })
-        );
-        sql.run(get_tags_cmd).unwrap(); 
+/// Tags ordered by the number of articles using them; with `days` only articles of the last days count
+/// and unused tags are left out. All tags are returned unless `limit` is given.
+fn get_tags_handler(req: Request, res: Response, p: Params) {
+    let conditions = conditional::Conditions::from_headers(&req.headers);
+    if let Some(q) = p.query().get("q") {
+        let limit = match p.query().limit(query::DEFAULT_SUGGESTION_LIMIT, query::MAX_SUGGESTION_LIMIT) {
+            Ok(limit) => limit,
+            Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+        };
+        return suggest_tags(res, &conditions, q, limit);
     }
 
-    res.headers_mut().set(
-        AccessControlAllowOrigin::Any
+    let limit = match p.query().get("limit") {
+        Some(_) => p.query().limit(query::DEFAULT_LIMIT, query::MAX_LIMIT),
+        None => Ok(std::i32::MAX),
+    };
+    let limit = match limit {
+        Ok(limit) => limit,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let days : i32 = match p.query().parse_or("days", 0) {
+        Ok(days) if days >= 0 => days,
+        Ok(days) => return send_error(res, StatusCode::UnprocessableEntity, &format!("days must not be negative, got {}", days)),
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+
+    let counts = fetch_rows(
+        r#"DECLARE @limit int = @P1; DECLARE @days int = @P2;"#,
+        r#"SELECT TOP (@limit) Tag, Counted.ArticlesCount FROM [dbo].[Tags]
+        CROSS APPLY ( SELECT COUNT(*) AS ArticlesCount FROM ArticleTags INNER JOIN Articles ON Articles.Id = ArticleTags.ArticleId
+            WHERE ArticleTags.TagId = Tags.Id AND Articles.[CurrentStatus] = 'published'
+            AND ( @days = 0 OR Articles.Created >= DATEADD(day, -@days, GETUTCDATE()) ) ) AS Counted
+        WHERE @days = 0 OR Counted.ArticlesCount > 0
+        ORDER BY Counted.ArticlesCount DESC, Tag"#,
+        get_tag_count_from_row,
+        &[&limit, &days]
     );
-    res.headers_mut().set(
-        ContentType(Mime(TopLevel::Application, SubLevel::Json,
-                    vec![(Attr::Charset, Value::Utf8)]))
-    );            
+    let result = GetTagsResult{ tags: counts.iter().map(|count| count.tag.to_string()).collect(), tagCounts: Some(counts) };
+    conditional::send_cached(res, &conditions, &conditional::TAGS_CACHE, None, None, &result);
+}
 
-    if result.is_some() {
-        let result = result.unwrap();
-        let result = serde_json::to_string(&result).unwrap();
-        let result : &[u8] = result.as_bytes();
-        res.send(&result).unwrap();                        
-    }   
+#[cfg(unix)]
+fn listen_on_unix_socket<H: hyper::server::Handler + 'static>(path: &str, handler: H) -> hyper::server::Listening {
+    Server::new(shutdown::Closable::new(listener::UnixHttpListener::bind(path).unwrap())).handle(handler).unwrap()
 }
 
-fn main() {    
-    let port = iis::get_port();
+#[cfg(not(unix))]
+fn listen_on_unix_socket<H: hyper::server::Handler + 'static>(path: &str, _: H) -> hyper::server::Listening {
+    panic!("unix_socket {} configured, but Unix domain sockets are not supported on this platform", path);
+}
+
+#[cfg(unix)]
+fn connect_unix_socket(path: &str) {
+    let _ = std::os::unix::net::UnixStream::connect(path);
+}
 
-    let listen_on = format!("127.0.0.1:{}", port);
+#[cfg(not(unix))]
+fn connect_unix_socket(_: &str) {}
 
-    println!("Listening on {}", listen_on);
+/// Our own address as a client sees it, a wildcard bind is reached over loopback.
+fn connect_address(socket: std::net::SocketAddr) -> std::net::SocketAddr {
+    let ip = match socket.ip() {
+        std::net::IpAddr::V4(ip) if ip.is_unspecified() => std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1)),
+        std::net::IpAddr::V6(ip) if ip.is_unspecified() => std::net::IpAddr::V6(std::net::Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+        ip => ip,
+    };
+    std::net::SocketAddr::new(ip, socket.port())
+}
+
+#[cfg(feature = "tls")]
+fn listen_with_tls<H: hyper::server::Handler + 'static>(listen_on: &str, handler: H) -> hyper::server::Listening {
+    let tls_settings = SETTINGS.tls.as_ref().unwrap();
+    let reloadable = tls::ReloadableTls::new(tls_settings).unwrap();
+    tls::reload_on_sighup(reloadable.clone());
+    if let Some(redirect_port) = tls_settings.redirect_port {
+        // the redirect listener lives until the process exits
+        std::mem::forget(tls::redirect_to_https(&SETTINGS.server.bind, redirect_port, SETTINGS.server.port));
+    }
+    let listener = hyper::net::HttpsListener::new(listen_on, reloadable).unwrap();
+    Server::new(shutdown::Closable::new(listener)).handle(handler).unwrap()
+}
+
+#[cfg(not(feature = "tls"))]
+fn listen_with_tls<H: hyper::server::Handler + 'static>(listen_on: &str, _: H) -> hyper::server::Listening {
+    panic!("TLS configured for {}, but the server was built without the tls feature", listen_on);
+}
+
+fn main() {    
+    lazy_static::initialize(&SETTINGS);
+    let loading = search::load();
+    let scheduler = scheduler::start(Duration::from_secs(SETTINGS.articles.publish_interval));
+    let server_config = &SETTINGS.server;
 
     let mut builder = RouterBuilder::new();
 
-    // Use raw strings so you don't need to escape patterns.
-    builder.get(r"/", hello_handler);   
-    builder.post(r"/createdb", create_db_handler);   
-    builder.post(r"/api/users/login", authentication_handler);   
-    builder.post(r"/api/users", registration_handler);   
-    builder.get(r"/api/user", get_current_user_handler);   
-    builder.get(r"/test", test_handler);   
-    builder.put(r"/api/user", update_user_handler);   
-    builder.get(r"/api/profiles/.*", get_profile_handler);   
-    builder.post(r"/api/profiles/.*/follow", follow_handler);   
-    builder.delete(r"/api/profiles/.*/follow", unfollow_handler);  
-    builder.post(r"/api/articles", create_article_handler);   
-    builder.get(r"/api/tags", get_tags_handler);   
-    builder.post(r"/api/articles/.*/comments", add_comment_handler);  
-    builder.post(r"/api/articles/.*/favorite", favorite_article_handler);  
-    builder.delete(r"/api/articles/.*/favorite", unfavorite_article_handler);
-    builder.put(r"/api/articles/.*", update_article_handler);   
-    builder.delete(r"/api/articles/.*/comments/.*", delete_comment_handler);  
-    builder.delete(r"/api/articles/.*", delete_article_handler);  
-    builder.get(r"/api/articles/feed", feed_handler);  
-    builder.get(r"/api/articles/.*/comments", get_comments_handler);  
-    builder.get(r"/api/articles/.*", get_article_handler);  
-    builder.get(r"/api/articles?.*", list_article_handler); 
-    builder.options("/api/.*", options_handler);
-
-    let router = builder.finalize().unwrap(); 
-
-    Server::http(listen_on).unwrap().handle(router).unwrap();  
+    builder.get("/", hello_handler);   
+    builder.post("/createdb", create_db_handler);   
+    builder.post("/api/users/login", authentication_handler);   
+    builder.post("/api/users", registration_handler);   
+    builder.get("/api/user", get_current_user_handler);   
+    builder.get("/test", test_handler);   
+    builder.put("/api/user", update_user_handler);   
+    builder.get("/api/profiles", search_profiles_handler);
+    builder.get("/api/profiles/:username", get_profile_handler);   
+    builder.post("/api/profiles/:username/follow", follow_handler);   
+    builder.delete("/api/profiles/:username/follow", unfollow_handler);  
+    builder.post("/api/articles", create_article_handler);   
+    builder.get("/api/tags", get_tags_handler);   
+    builder.get("/api/tags/:tag", tag::get_tag_handler);
+    builder.post("/api/tags/:tag/follow", tag::follow_tag_handler);
+    builder.delete("/api/tags/:tag/follow", tag::unfollow_tag_handler);
+    builder.post("/api/articles/:slug/comments", add_comment_handler);  
+    builder.post("/api/articles/:slug/favorite", favorite_article_handler);  
+    builder.delete("/api/articles/:slug/favorite", unfavorite_article_handler);
+    builder.put("/api/articles/:slug", update_article_handler);   
+    builder.post("/api/articles/:slug/publish", publish_article_handler);
+    builder.delete("/api/articles/:slug/publish", unpublish_article_handler);
+    builder.get("/api/articles/:slug/revisions", revision::get_revisions_handler);
+    builder.get("/api/articles/:slug/revisions/:n", revision::get_revision_handler);
+    builder.get("/api/articles/:slug/revisions/:n/diff", revision::diff_revisions_handler);
+    builder.post("/api/articles/:slug/revisions/:n/restore", revision::restore_revision_handler);
+    builder.delete("/api/articles/:slug/comments/:id", delete_comment_handler);  
+    builder.delete("/api/articles/:slug", delete_article_handler);  
+    builder.get("/api/articles/feed", feed_handler);  
+    builder.get("/api/articles/:slug/comments", get_comments_handler);  
+    builder.get("/api/articles/:slug", get_article_handler);  
+    builder.get("/api/articles", list_article_handler); 
+    builder.get("/api/search", search::search_handler);
+    builder.post("/api/admin/tags/merge", tag::merge_tags_handler);
+    builder.post("/api/admin/tags/aliases", tag::add_tag_alias_handler);
+    builder.put("/api/admin/tags/:tag", tag::update_tag_handler);
+
+    let router = builder.finalize(); 
+
+    let shutdown_timeout = Duration::from_secs(server_config.shutdown_timeout);
+
+    let handler = shutdown::Draining::new(cors::Cors::new(router, &SETTINGS.cors));
+    let mut listening = match server_config.unix_socket {
+        Some(ref path) => {
+            println!("Listening on {}", path);
+            listen_on_unix_socket(path, handler)
+        }
+        None => {
+            let listen_on = format!("{}:{}", server_config.bind, server_config.port);
+            if SETTINGS.tls.is_some() {
+                println!("Listening on {} (HTTPS)", listen_on);
+                listen_with_tls(&listen_on, handler)
+            } else {
+                println!("Listening on {}", listen_on);
+                let listener = hyper::net::HttpListener::new(&listen_on[..]).unwrap();
+                Server::new(shutdown::Closable::new(listener)).handle(handler).unwrap()
+            }
+        }
+    };
 
+    shutdown::wait_for_signal();
+    let socket = connect_address(listening.socket);
+    shutdown::stop_accepting(|| match server_config.unix_socket {
+        Some(ref path) => connect_unix_socket(path),
+        None => { let _ = std::net::TcpStream::connect_timeout(&socket, Duration::from_millis(100)); }
+    });
+    let drained = shutdown::drain(shutdown_timeout);
+    let _ = scheduler.join();
+    let _ = loading.join();
+    let _ = listening.close();
+
+    std::process::exit(if drained { 0 } else { 1 });
 }
@@ -0,0 +1,96 @@
+extern crate url;
+
+use std::str::FromStr;
+
+use url::form_urlencoded;
+
+pub static DEFAULT_LIMIT : i32 = 20;
+pub static MAX_LIMIT : i32 = 100;
+pub static DEFAULT_SUGGESTION_LIMIT : i32 = 10;
+pub static MAX_SUGGESTION_LIMIT : i32 = 25;
+
+/// Decoded query string; `+` and `%XX` are decoded and repeated keys are kept in order.
+#[derive(Debug, Default)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+/// `limit` and `offset` of a list request.
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub limit: i32,
+    pub offset: i32,
+}
+
+impl Query {
+    pub fn parse(raw: &str) -> Query {
+        Query { pairs: form_urlencoded::parse(raw.as_bytes()).into_owned().collect() }
+    }
+
+    /// First value of `name`; empty values count as missing.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).into_iter().next()
+    }
+
+    /// All non-empty values of `name`, e.g. `tag=a&tag=b`.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.pairs.iter()
+            .filter(|&&(ref n, ref v)| n == name && !v.is_empty())
+            .map(|&(_, ref v)| v.as_str())
+            .collect()
+    }
+
+    /// Parses `name`, falling back to `default` when missing; the error is meant for a 422 response.
+    pub fn parse_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get(name) {
+            Some(value) => value.parse::<T>().map_err(|_| format!("{} '{}' is not valid", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    /// `limit` falling back to `default` and clamped to `max`.
+    pub fn limit(&self, default: i32, max: i32) -> Result<i32, String> {
+        let limit : i32 = self.parse_or("limit", default)?;
+        if limit < 1 {
+            return Err(format!("limit must be at least 1, got {}", limit));
+        }
+        Ok(if limit > max { max } else { limit })
+    }
+
+    /// `limit` defaults to `DEFAULT_LIMIT` and is clamped to `MAX_LIMIT`, `offset` defaults to 0.
+    pub fn page(&self) -> Result<Page, String> {
+        let limit = self.limit(DEFAULT_LIMIT, MAX_LIMIT)?;
+        let offset : i32 = self.parse_or("offset", 0)?;
+        if offset < 0 {
+            return Err(format!("offset must not be negative, got {}", offset));
+        }
+        Ok(Page { limit: limit, offset: offset })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn query_decoding_test() {
+    let query = Query::parse("tag=rust+lang&author=Jacob%20Smith&tag=%C5%BElu%C5%A5ou%C4%8Dk%C3%BD&favorited=");
+    assert_eq!(query.get("tag"), Some("rust lang"));
+    assert_eq!(query.get_all("tag"), vec!["rust lang", "žluťoučký"]);
+    assert_eq!(query.get("author"), Some("Jacob Smith"));
+    assert_eq!(query.get("favorited"), None);
+    assert_eq!(query.get("missing"), None);
+}
+
+#[cfg(test)]
+#[test]
+fn page_test() {
+    assert_eq!(Query::parse("").page(), Ok(Page { limit: DEFAULT_LIMIT, offset: 0 }));
+    assert_eq!(Query::parse("limit=5&offset=10").page(), Ok(Page { limit: 5, offset: 10 }));
+    assert_eq!(Query::parse("limit=100000").page(), Ok(Page { limit: MAX_LIMIT, offset: 0 }));
+    assert!(Query::parse("limit=ten").page().is_err());
+    assert!(Query::parse("limit=0").page().is_err());
+    assert!(Query::parse("offset=-1").page().is_err());
+    assert!(Query::parse("offset=99999999999").page().is_err());
+
+    assert_eq!(Query::parse("").limit(10, 25), Ok(10));
+    assert_eq!(Query::parse("limit=100").limit(10, 25), Ok(25));
+    assert!(Query::parse("limit=-1").limit(10, 25).is_err());
+}
@@ -0,0 +1,356 @@
+extern crate hyper;
+extern crate serde;
+extern crate serde_json;
+extern crate tiberius;
+
+use chrono::prelude::*;
+
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+
+use router::Params;
+
+use super::*;
+
+/// Title, description and body of an article as they were after one write; revision 1 is the created article.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct Revision {
+    number: i32,
+    title: String,
+    description: String,
+    body: String,
+    /// User name of whoever wrote the revision.
+    author: String,
+    createdAt: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct RevisionResult {
+    revision: Revision,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct RevisionsResult {
+    /// Newest first.
+    revisions: Vec<Revision>,
+    revisionsCount: usize,
+}
+
+/// One line of a diff: `unchanged`, `added` or `removed`.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq)]
+struct DiffLine {
+    change: String,
+    line: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct RevisionDiff {
+    from: i32,
+    to: i32,
+    title: Vec<DiffLine>,
+    description: Vec<DiffLine>,
+    body: Vec<DiffLine>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct RevisionDiffResult {
+    diff: RevisionDiff,
+}
+
+static REVISION_SELECT : &'static str = r#"
+  SELECT [Number], ArticleRevisions.Title, ArticleRevisions.[Description], ArticleRevisions.Body, Users.UserName, ArticleRevisions.Created
+  FROM ArticleRevisions INNER JOIN Users ON Users.Id = ArticleRevisions.Author
+  WHERE ArticleId = @id AND ( @number = 0 OR [Number] = @number )
+  ORDER BY [Number] DESC
+"#;
+
+fn get_revision_from_row( row : tiberius::query::QueryRow ) -> Option<Revision> {
+    let number : i32 = row.get(0);
+    let title : &str = row.get(1);
+    let description : &str = row.get(2);
+    let body : &str = row.get(3);
+    let author : &str = row.get(4);
+    let created_at : NaiveDateTime = row.get(5);
+    Some(Revision{
+        number: number, title: title.to_string(), description: description.to_string(), body: body.to_string(),
+        author: author.to_string(), createdAt: created_at,
+    })
+}
+
+fn get_authored_id_from_row( row : tiberius::query::QueryRow ) -> Option<(i32, bool)> {
+    let id : i32 = row.get(0);
+    let authored : i32 = row.get(1);
+    Some((id, authored == 1))
+}
+
+/// Id of the logged in user's article behind `slug`. Revisions keep draft text and text the author
+/// removed later, so nobody else reads them. The error is meant for the response.
+fn authored_article_id(slug: &str, logged_id: i32) -> Result<i32, (StatusCode, &'static str)> {
+    if logged_id == 0 {
+        return Err((StatusCode::Unauthorized, "login required"));
+    }
+    let found = fetch_rows(
+        "",
+        "SELECT Id, CASE WHEN Author = @P2 THEN 1 ELSE 0 END FROM Articles WHERE Id = dbo.VisibleArticleId(@P1, @P2)",
+        get_authored_id_from_row,
+        &[&slug, &logged_id]
+    ).pop();
+    match found {
+        Some((id, true)) => Ok(id),
+        Some((_, false)) => Err((StatusCode::Forbidden, "only the author can see and restore revisions")),
+        None => Err((StatusCode::NotFound, "article not found")),
+    }
+}
+
+/// All revisions of the article when `number` is 0.
+fn fetch_revisions(id: i32, number: i32) -> Vec<Revision> {
+    fetch_rows("DECLARE @id int = @P1; DECLARE @number int = @P2;", REVISION_SELECT, get_revision_from_row, &[&id, &number])
+}
+
+/// Line diff of `from` to `to` along their longest common subsequence of lines.
+fn diff_lines(from: &str, to: &str) -> Vec<DiffLine> {
+    let from : Vec<&str> = from.lines().collect();
+    let to : Vec<&str> = to.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of from[i..] and to[j..]
+    let mut common = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            common[i][j] = if from[i] == to[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(common[i + 1][j], common[i][j + 1])
+            };
+        }
+    }
+
+    let line = |change: &str, line: &str| DiffLine{ change: change.to_string(), line: line.to_string() };
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() && j < to.len() {
+        if from[i] == to[j] {
+            diff.push(line("unchanged", from[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            diff.push(line("removed", from[i]));
+            i += 1;
+        } else {
+            diff.push(line("added", to[j]));
+            j += 1;
+        }
+    }
+    diff.extend(from[i..].iter().map(|l| line("removed", l)));
+    diff.extend(to[j..].iter().map(|l| line("added", l)));
+    diff
+}
+
+pub fn get_revisions_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let slug = p.get("slug");
+    println!("get_revisions_handler slug: '{}'", slug);
+
+    let id = match authored_article_id(slug, logged_id) {
+        Ok(id) => id,
+        Err((status, message)) => return send_error(res, status, message),
+    };
+    let revisions = fetch_revisions(id, 0);
+    let count = revisions.len();
+    send_json(res, &RevisionsResult{ revisions: revisions, revisionsCount: count });
+}
+
+pub fn get_revision_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let slug = p.get("slug");
+    let number : i32 = match p.parse("n") {
+        Ok(number) if number > 0 => number,
+        Ok(_) => return send_error(res, StatusCode::BadRequest, "revisions are numbered from 1"),
+        Err(e) => return send_error(res, StatusCode::BadRequest, &e),
+    };
+    println!("get_revision_handler slug: '{}' revision: {}", slug, number);
+
+    let id = match authored_article_id(slug, logged_id) {
+        Ok(id) => id,
+        Err((status, message)) => return send_error(res, status, message),
+    };
+    match fetch_revisions(id, number).pop() {
+        Some(revision) => send_json(res, &RevisionResult{ revision: revision }),
+        None => send_error(res, StatusCode::NotFound, &format!("revision {} not found", number)),
+    }
+}
+
+/// Diff of revision `:n` against the one in `from`, the revision before it by default.
+pub fn diff_revisions_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let slug = p.get("slug");
+    let to : i32 = match p.parse("n") {
+        Ok(to) if to > 0 => to,
+        Ok(_) => return send_error(res, StatusCode::BadRequest, "revisions are numbered from 1"),
+        Err(e) => return send_error(res, StatusCode::BadRequest, &e),
+    };
+    let from : i32 = match p.query().get("from").map(|from| from.parse::<i32>()) {
+        Some(Ok(from)) if from > 0 => from,
+        Some(_) => return send_error(res, StatusCode::BadRequest, "from must be a revision number"),
+        None if to > 1 => to - 1,
+        None => return send_error(res, StatusCode::BadRequest, "revision 1 has no previous revision, give from"),
+    };
+    println!("diff_revisions_handler slug: '{}' revisions: {}..{}", slug, from, to);
+
+    let id = match authored_article_id(slug, logged_id) {
+        Ok(id) => id,
+        Err((status, message)) => return send_error(res, status, message),
+    };
+    let (from_revision, to_revision) = match (fetch_revisions(id, from).pop(), fetch_revisions(id, to).pop()) {
+        (Some(from_revision), Some(to_revision)) => (from_revision, to_revision),
+        (None, _) => return send_error(res, StatusCode::NotFound, &format!("revision {} not found", from)),
+        (_, None) => return send_error(res, StatusCode::NotFound, &format!("revision {} not found", to)),
+    };
+    send_json(res, &RevisionDiffResult{ diff: RevisionDiff{
+        from: from,
+        to: to,
+        title: diff_lines(&from_revision.title, &to_revision.title),
+        description: diff_lines(&from_revision.description, &to_revision.description),
+        body: diff_lines(&from_revision.body, &to_revision.body),
+    }});
+}
+
+/// Puts the title, description and body of revision `:n` back, recording them as a new revision.
+/// The slug stays as it is, links to the article keep working.
+pub fn restore_revision_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let slug = p.get("slug");
+    let number : i32 = match p.parse("n") {
+        Ok(number) if number > 0 => number,
+        Ok(_) => return send_error(res, StatusCode::BadRequest, "revisions are numbered from 1"),
+        Err(e) => return send_error(res, StatusCode::BadRequest, &e),
+    };
+    println!("restore_revision_handler slug: '{}' revision: {}", slug, number);
+
+    let id = match authored_article_id(slug, logged_id) {
+        Ok(id) => id,
+        Err((status, message)) => return send_error(res, status, message),
+    };
+    if fetch_revisions(id, number).is_empty() {
+        return send_error(res, StatusCode::NotFound, &format!("revision {} not found", number));
+    }
+
+    process_with(
+        res,
+        r#"DECLARE @id int = @P1; DECLARE @logged int = @P2;
+        BEGIN TRANSACTION;
+        UPDATE Articles SET Title = ArticleRevisions.Title, [Description] = ArticleRevisions.[Description], Body = ArticleRevisions.Body,
+            Updated = GETUTCDATE()
+            FROM Articles INNER JOIN ArticleRevisions ON ArticleRevisions.ArticleId = Articles.Id AND ArticleRevisions.[Number] = @P3
+            WHERE Articles.Id = @id AND Articles.Author = @logged;
+        IF @@ROWCOUNT = 1 EXEC dbo.AddArticleRevision @id, @logged;
+        COMMIT;
+        "#,
+        ARTICLE_SELECT,
+        get_article_from_row,
+        &[&id, &logged_id, &number],
+        |restored: &CreateArticleResult| search::refresh(&[restored.article.slug.as_str()])
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn diff_lines_test() {
+    let change = |diff: &[DiffLine]| diff.iter().map(|l| format!("{} {}", &l.change[..1], l.line)).collect::<Vec<String>>();
+    assert_eq!(change(&diff_lines("a\nb\nc", "a\nc\nd")), vec!["u a", "r b", "u c", "a d"]);
+    assert_eq!(change(&diff_lines("", "a")), vec!["a a"]);
+    assert_eq!(change(&diff_lines("a", "")), vec!["r a"]);
+    assert!(diff_lines("same", "same").iter().all(|l| l.change == "unchanged"));
+}
+
+#[cfg(test)]
+fn get_json<T: serde::de::DeserializeOwned>(url: &str, jwt: &str) -> T {
+    let client = Client::new();
+    let mut res = client.get(url).header(Authorization(Bearer {token: jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    serde_json::from_str(&buffer).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn article_revisions_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let (other_jwt, _, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+    for body in &["You have to believe\\nand fly", "You have to fly"] {
+        let res = client.put(&url)
+            .header(Authorization(Bearer {token: jwt.to_owned()}))
+            .body(&format!(r#"{{"article": {{"body": "{}"}}}}"#, body))
+            .send()
+            .unwrap();
+        assert_eq!(res.status, hyper::Ok);
+    }
+
+    let revisions : RevisionsResult = get_json(&format!("{}/revisions", url), &jwt);
+    assert_eq!(revisions.revisionsCount, 3);
+    assert_eq!(revisions.revisions.iter().map(|r| r.number).collect::<Vec<i32>>(), vec![3, 2, 1]);
+
+    let first : RevisionResult = get_json(&format!("{}/revisions/1", url), &jwt);
+    assert_eq!(first.revision.body, "You have to believe");
+
+    let diff : RevisionDiffResult = get_json(&format!("{}/revisions/3/diff?from=1", url), &jwt);
+    assert_eq!(diff.diff.body, vec![
+        DiffLine{ change: "removed".to_string(), line: "You have to believe".to_string() },
+        DiffLine{ change: "added".to_string(), line: "You have to fly".to_string() },
+    ]);
+    assert!(diff.diff.title.iter().all(|l| l.change == "unchanged"));
+
+    let res = client.get(&format!("{}/revisions/9", url)).header(Authorization(Bearer {token: jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, StatusCode::NotFound);
+
+    // the history may hold text the author took out, only they read it
+    let res = client.get(&format!("{}/revisions/1", url)).header(Authorization(Bearer {token: other_jwt.to_owned()})).send().unwrap();
+    assert_eq!(res.status, StatusCode::Forbidden);
+    let res = client.get(&format!("{}/revisions", url)).send().unwrap();
+    assert_eq!(res.status, StatusCode::Unauthorized);
+
+    let res = client.post(&format!("{}/revisions/1/restore", url))
+        .header(Authorization(Bearer {token: other_jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::Forbidden);
+
+    // 0 is not "all revisions" here
+    let res = client.post(&format!("{}/revisions/0/restore", url))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::BadRequest);
+
+    let mut res = client.post(&format!("{}/revisions/1/restore", url))
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let restored : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(restored.article.body, "You have to believe");
+    assert_eq!(restored.article.slug, slug);
+
+    let revisions : RevisionsResult = get_json(&format!("{}/revisions", url), &jwt);
+    assert_eq!(revisions.revisionsCount, 4);
+    assert_eq!(revisions.revisions[0].body, "You have to believe");
+}
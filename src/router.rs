@@ -0,0 +1,360 @@
+extern crate hyper;
+extern crate url;
+
+use std::str::FromStr;
+use std::str;
+
+use hyper::server::{Handler, Request, Response};
+use hyper::status::StatusCode;
+use hyper::method::Method;
+use hyper::uri::RequestUri;
+use hyper::header::{Headers, Allow};
+
+use url::percent_encoding::percent_decode;
+
+use super::send_error;
+use query::Query;
+
+/// Path parameters of the matched route and the query string, both percent-decoded.
+#[derive(Debug)]
+pub struct Params {
+    values: Vec<(&'static str, String)>,
+    query: Query,
+}
+
+impl Params {
+    /// Value of a parameter declared in the route pattern, `:name` in `/api/profiles/:name`.
+    pub fn get(&self, name: &str) -> &str {
+        self.values.iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, ref value)| value.as_str())
+            .unwrap_or_else(|| panic!("route has no parameter :{}", name))
+    }
+
+    /// Parses a parameter, the error is meant for a 400 response.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, String> {
+        let value = self.get(name);
+        value.parse::<T>().map_err(|_| format!("invalid {} '{}'", name, value))
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+}
+
+pub type RouteHandler = fn(Request, Response, Params);
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(&'static str),
+    Param(&'static str),
+}
+
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: RouteHandler,
+}
+
+fn parse_pattern(pattern: &'static str) -> Vec<Segment> {
+    pattern.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| if s.starts_with(':') { Segment::Param(&s[1..]) } else { Segment::Literal(s) })
+        .collect()
+}
+
+/// Splits the request target into decoded path segments and the raw query string.
+/// Returns `None` when a segment does not decode to UTF-8.
+fn split_uri(uri: &str) -> Option<(Vec<String>, String)> {
+    let (path, query) = match uri.find('?') {
+        Some(i) => (&uri[..i], &uri[i + 1..]),
+        None => (uri, ""),
+    };
+    let mut segments = Vec::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        match percent_decode(segment.as_bytes()).decode_utf8() {
+            Ok(decoded) => segments.push(decoded.into_owned()),
+            Err(_) => return None,
+        }
+    }
+    Some((segments, query.to_string()))
+}
+
+impl Route {
+    /// Literal segments rank above parameters, compared from the left,
+    /// so `/api/articles/feed` wins over `/api/articles/:slug` whatever the registration order.
+    fn specificity(&self) -> Vec<bool> {
+        self.segments.iter().map(|s| match *s { Segment::Literal(_) => true, Segment::Param(_) => false }).collect()
+    }
+
+    fn same_shape(&self, other: &Route) -> bool {
+        self.segments.len() == other.segments.len()
+            && self.segments.iter().zip(&other.segments).all(|(a, b)| match (a, b) {
+                (&Segment::Literal(a), &Segment::Literal(b)) => a == b,
+                (&Segment::Param(_), &Segment::Param(_)) => true,
+                _ => false,
+            })
+    }
+
+    fn matches(&self, segments: &[String]) -> Option<Vec<(&'static str, String)>> {
+        if self.segments.len() != segments.len() {
+            return None;
+        }
+        let mut values = Vec::new();
+        for (pattern, segment) in self.segments.iter().zip(segments) {
+            match *pattern {
+                Segment::Literal(literal) => if literal != segment { return None; },
+                Segment::Param(name) => values.push((name, segment.clone())),
+            }
+        }
+        Some(values)
+    }
+}
+
+pub struct RouterBuilder {
+    routes: Vec<Route>,
+}
+
+impl RouterBuilder {
+    pub fn new() -> RouterBuilder {
+        RouterBuilder { routes: Vec::new() }
+    }
+
+    /// Registers `handler` for `pattern`, e.g. `/api/articles/:slug/comments/:id`.
+    pub fn route(&mut self, method: Method, pattern: &'static str, handler: RouteHandler) {
+        self.routes.push(Route { method: method, segments: parse_pattern(pattern), handler: handler });
+    }
+
+    pub fn get(&mut self, pattern: &'static str, handler: RouteHandler) {
+        self.route(Method::Get, pattern, handler);
+    }
+
+    pub fn post(&mut self, pattern: &'static str, handler: RouteHandler) {
+        self.route(Method::Post, pattern, handler);
+    }
+
+    pub fn put(&mut self, pattern: &'static str, handler: RouteHandler) {
+        self.route(Method::Put, pattern, handler);
+    }
+
+    pub fn delete(&mut self, pattern: &'static str, handler: RouteHandler) {
+        self.route(Method::Delete, pattern, handler);
+    }
+
+    pub fn options(&mut self, pattern: &'static str, handler: RouteHandler) {
+        self.route(Method::Options, pattern, handler);
+    }
+
+    /// Orders the routes most specific first; panics when two routes would be ambiguous.
+    pub fn finalize(mut self) -> Router {
+        for (i, route) in self.routes.iter().enumerate() {
+            for other in &self.routes[i + 1..] {
+                if route.method == other.method && route.same_shape(other) {
+                    panic!("ambiguous routes for {} {:?} and {:?}", route.method, route.segments, other.segments);
+                }
+            }
+        }
+        self.routes.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
+        Router { routes: self.routes }
+    }
+}
+
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+enum Resolution {
+    Found(RouteHandler, Vec<(&'static str, String)>),
+    /// HEAD served by the GET handler with the body discarded.
+    Head(RouteHandler, Vec<(&'static str, String)>),
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+impl Router {
+    fn resolve(&self, method: &Method, segments: &[String]) -> Resolution {
+        let mut allowed = Vec::new();
+        let mut get = None;
+        for route in &self.routes {
+            if let Some(values) = route.matches(segments) {
+                if route.method == *method {
+                    return Resolution::Found(route.handler, values);
+                }
+                if route.method == Method::Get && get.is_none() {
+                    get = Some((route.handler, values));
+                }
+                if !allowed.contains(&route.method) {
+                    allowed.push(route.method.clone());
+                }
+            }
+        }
+
+        if *method == Method::Head {
+            if let Some((handler, values)) = get {
+                return Resolution::Head(handler, values);
+            }
+        }
+        if allowed.is_empty() {
+            return Resolution::NotFound;
+        }
+        if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+            allowed.push(Method::Head);
+        }
+        Resolution::MethodNotAllowed(allowed)
+    }
+
+    #[cfg(test)]
+    fn find(&self, method: &Method, segments: &[String]) -> Option<(RouteHandler, Vec<(&'static str, String)>)> {
+        match self.resolve(method, segments) {
+            Resolution::Found(handler, values) => Some((handler, values)),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the GET handler into a buffer and sends only its status and headers.
+fn handle_head(handler: RouteHandler, req: Request, mut res: Response, params: Params) {
+    let mut buffer : Vec<u8> = Vec::new();
+    let mut headers = Headers::new();
+    {
+        let inner = Response::new(&mut buffer, &mut headers);
+        handler(req, inner, params);
+    }
+
+    // the buffer starts with the status line, e.g. "HTTP/1.1 200 OK"
+    let status = buffer.split(|&b| b == b'\r').next()
+        .and_then(|line| str::from_utf8(line).ok())
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .map(StatusCode::from_u16)
+        .unwrap_or(StatusCode::InternalServerError);
+
+    *res.status_mut() = status;
+    res.headers_mut().extend(headers.iter());
+    if let Ok(res) = res.start() {
+        let _ = res.end();
+    }
+}
+
+impl Handler for Router {
+    fn handle<'a, 'k>(&'a self, req: Request<'a, 'k>, mut res: Response<'a>) {
+        let uri = match req.uri {
+            RequestUri::AbsolutePath(ref uri) => split_uri(uri),
+            _ => None,
+        };
+        let (segments, query) = match uri {
+            Some(uri) => uri,
+            None => return send_error(res, StatusCode::BadRequest, "path is not valid UTF-8"),
+        };
+
+        match self.resolve(&req.method, &segments) {
+            Resolution::Found(handler, values) => handler(req, res, Params { values: values, query: Query::parse(&query) }),
+            Resolution::Head(handler, values) => handle_head(handler, req, res, Params { values: values, query: Query::parse(&query) }),
+            Resolution::MethodNotAllowed(allowed) => {
+                let message = format!("{} is not allowed here", req.method);
+                res.headers_mut().set(Allow(allowed));
+                send_error(res, StatusCode::MethodNotAllowed, &message);
+            }
+            Resolution::NotFound => {
+                let message = format!("no route for {}", req.uri);
+                send_error(res, StatusCode::NotFound, &message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn route_test_handler(_: Request, _: Response, _: Params) {}
+
+#[cfg(test)]
+#[test]
+fn split_uri_test() {
+    let (segments, query) = split_uri("/api/profiles/Jacob%20Smith/follow?x=1").unwrap();
+    assert_eq!(segments, vec!["api", "profiles", "Jacob Smith", "follow"]);
+    assert_eq!(query, "x=1");
+
+    let (segments, _) = split_uri("/api/articles/%C5%BElu%C5%A5ou%C4%8Dk%C3%BD-k%C5%AF%C5%88").unwrap();
+    assert_eq!(segments[2], "žluťoučký-kůň");
+
+    assert!(split_uri("/api/articles/%FF").is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn route_params_test() {
+    let mut builder = RouterBuilder::new();
+    builder.delete("/api/articles/:slug/comments/:id", route_test_handler);
+    let router = builder.finalize();
+
+    let segments : Vec<String> = vec!["api", "articles", "how-to", "comments", "12"].iter().map(|s| s.to_string()).collect();
+    let (_, values) = router.find(&Method::Delete, &segments).unwrap();
+    let params = Params { values: values, query: Query::default() };
+    assert_eq!(params.get("slug"), "how-to");
+    assert_eq!(params.parse::<i32>("id"), Ok(12));
+
+    let segments : Vec<String> = vec!["api", "articles", "how-to", "comments", "twelve"].iter().map(|s| s.to_string()).collect();
+    let (_, values) = router.find(&Method::Delete, &segments).unwrap();
+    let params = Params { values: values, query: Query::default() };
+    assert!(params.parse::<i32>("id").is_err());
+
+    assert!(router.find(&Method::Get, &segments).is_none());
+    assert!(router.find(&Method::Delete, &segments[..4]).is_none());
+}
+
+#[cfg(test)]
+fn other_test_handler(_: Request, _: Response, _: Params) {}
+
+#[cfg(test)]
+fn segments(path: &str) -> Vec<String> {
+    split_uri(path).unwrap().0
+}
+
+#[cfg(test)]
+#[test]
+fn most_specific_route_test() {
+    let mut builder = RouterBuilder::new();
+    builder.get("/api/articles/:slug", route_test_handler);
+    builder.get("/api/articles/feed", other_test_handler);
+    builder.get("/api/articles/:slug/comments", route_test_handler);
+    let router = builder.finalize();
+
+    let (handler, values) = router.find(&Method::Get, &segments("/api/articles/feed")).unwrap();
+    assert!(handler as usize == other_test_handler as usize);
+    assert!(values.is_empty());
+
+    let (handler, values) = router.find(&Method::Get, &segments("/api/articles/feeding")).unwrap();
+    assert!(handler as usize == route_test_handler as usize);
+    assert_eq!(values, vec![("slug", "feeding".to_string())]);
+}
+
+#[cfg(test)]
+#[test]
+fn method_not_allowed_test() {
+    let mut builder = RouterBuilder::new();
+    builder.get("/api/articles/:slug", route_test_handler);
+    builder.put("/api/articles/:slug", route_test_handler);
+    let router = builder.finalize();
+
+    match router.resolve(&Method::Post, &segments("/api/articles/x")) {
+        Resolution::MethodNotAllowed(allowed) => assert_eq!(allowed, vec![Method::Get, Method::Put, Method::Head]),
+        _ => panic!("expected 405"),
+    }
+    match router.resolve(&Method::Head, &segments("/api/articles/x")) {
+        Resolution::Head(_, values) => assert_eq!(values, vec![("slug", "x".to_string())]),
+        _ => panic!("expected HEAD served by GET"),
+    }
+    match router.resolve(&Method::Get, &segments("/api/nothing")) {
+        Resolution::NotFound => {}
+        _ => panic!("expected 404"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic]
+fn ambiguous_routes_test() {
+    let mut builder = RouterBuilder::new();
+    builder.get("/api/articles/:slug", route_test_handler);
+    builder.get("/api/articles/:id", other_test_handler);
+    builder.finalize();
+}
@@ -0,0 +1,42 @@
+extern crate tiberius;
+
+use std::thread;
+use std::time::Duration;
+
+use super::*;
+
+/// Flips the scheduled articles whose `PublishAt` has passed to published, returning their slugs.
+/// Listings already treat them as published through `Articles.CurrentStatus`, this makes it permanent
+/// and puts them into the search index.
+static PUBLISH_DUE_COMMAND : &'static str = r#"
+  DECLARE @due TABLE (Slug nvarchar(250));
+  UPDATE Articles SET [Status] = 'published', PublishedAt = PublishAt, PublishAt = NULL
+    OUTPUT inserted.Slug INTO @due
+    WHERE [Status] = 'scheduled' AND PublishAt <= GETUTCDATE()
+"#;
+
+fn get_slug_from_row( row : tiberius::query::QueryRow ) -> Option<String> {
+    let slug : &str = row.get(0);
+    Some(slug.to_string())
+}
+
+fn publish_due() {
+    let slugs = fetch_rows(PUBLISH_DUE_COMMAND, "SELECT Slug FROM @due", get_slug_from_row, &[]);
+    if !slugs.is_empty() {
+        println!("Scheduler published {} articles", slugs.len());
+        let slugs : Vec<&str> = slugs.iter().map(|slug| slug.as_str()).collect();
+        search::refresh(&slugs);
+    }
+}
+
+/// Runs `publish_due` every `interval` until shutdown starts; a failing run
+/// (e.g. the database being unreachable) is logged and retried on the next tick.
+pub fn start(interval: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while shutdown::sleep(interval) {
+            if thread::spawn(publish_due).join().is_err() {
+                println!("Scheduler run failed, retrying in {:?}", interval);
+            }
+        }
+    })
+}
@@ -0,0 +1,530 @@
+extern crate hyper;
+extern crate serde_json;
+extern crate tiberius;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+
+use router::Params;
+
+use super::*;
+
+static TITLE_WEIGHT : f64 = 3.0;
+static TAG_WEIGHT : f64 = 2.0;
+static DESCRIPTION_WEIGHT : f64 = 1.5;
+static BODY_WEIGHT : f64 = 1.0;
+// BM25 parameters
+static K1 : f64 = 1.2;
+static B : f64 = 0.75;
+static SNIPPET_WORDS : usize = 30;
+static LOAD_RETRY_SECS : u64 = 10;
+
+/// All published articles when `@slug` is empty.
+static DOCUMENT_SELECT : &'static str = r#"
+  SELECT Articles.Id, Slug, Title, [Description], Body, Users.UserName,
+    (SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id) as Tags
+  FROM Articles INNER JOIN Users on Author=Users.Id
+  WHERE ( LEN(@slug) = 0 OR Slug = @slug ) AND Articles.[CurrentStatus] = 'published'
+"#;
+
+/// The searchable part of an article.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub id: i32,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub author: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub id: i32,
+    pub score: f64,
+}
+
+/// In-memory inverted index over all articles, ranked with BM25 on field-weighted term frequencies.
+#[derive(Debug, Default)]
+pub struct Index {
+    documents: HashMap<i32, Document>,
+    lengths: HashMap<i32, f64>,
+    postings: HashMap<String, HashMap<i32, f64>>,
+    /// While the initial load runs: the slugs `refresh` has seen since, their rows in the load are stale.
+    touched: Option<HashSet<String>>,
+}
+
+lazy_static! {
+    static ref INDEX : RwLock<Index> = RwLock::new(Index::default());
+    /// Held from reading the rows to applying them, so a slower refresh can't put back an older row.
+    static ref REFRESHING : Mutex<()> = Mutex::new(());
+}
+
+/// Byte ranges of the words in `text`.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push((s, text.len()));
+    }
+    ranges
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    words(text).into_iter().map(|(start, end)| text[start..end].to_lowercase()).collect()
+}
+
+fn weighted_terms(document: &Document) -> HashMap<String, f64> {
+    let mut terms = HashMap::new();
+    {
+        let mut add = |text: &str, weight: f64| {
+            for term in tokenize(text) {
+                *terms.entry(term).or_insert(0.0) += weight;
+            }
+        };
+        add(&document.title, TITLE_WEIGHT);
+        add(&document.description, DESCRIPTION_WEIGHT);
+        add(&document.body, BODY_WEIGHT);
+        for tag in &document.tags {
+            add(tag, TAG_WEIGHT);
+        }
+    }
+    terms
+}
+
+impl Index {
+    pub fn upsert(&mut self, document: Document) {
+        self.remove(document.id);
+        let terms = weighted_terms(&document);
+        self.lengths.insert(document.id, terms.values().sum());
+        for (term, frequency) in terms {
+            self.postings.entry(term).or_insert_with(HashMap::new).insert(document.id, frequency);
+        }
+        self.documents.insert(document.id, document);
+    }
+
+    pub fn remove(&mut self, id: i32) {
+        if let Some(document) = self.documents.remove(&id) {
+            for term in weighted_terms(&document).keys() {
+                let now_empty = match self.postings.get_mut(term) {
+                    Some(posting) => {
+                        posting.remove(&id);
+                        posting.is_empty()
+                    }
+                    None => false,
+                };
+                if now_empty {
+                    self.postings.remove(term);
+                }
+            }
+            self.lengths.remove(&id);
+        }
+    }
+
+    pub fn start_loading(&mut self) {
+        self.touched = Some(HashSet::new());
+    }
+
+    pub fn touch(&mut self, slug: &str) {
+        if let Some(ref mut touched) = self.touched {
+            touched.insert(slug.to_string());
+        }
+    }
+
+    /// Adds the documents of the initial load, except those written since it started, and returns how many.
+    pub fn finish_loading(&mut self, documents: Vec<Document>) -> usize {
+        let touched = self.touched.take().unwrap_or_default();
+        let mut count = 0;
+        for document in documents {
+            if !touched.contains(&document.slug) && !self.documents.contains_key(&document.id) {
+                self.upsert(document);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn remove_slug(&mut self, slug: &str) {
+        let id = self.documents.values().find(|d| d.slug == slug).map(|d| d.id);
+        if let Some(id) = id {
+            self.remove(id);
+        }
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Document> {
+        self.documents.get(&id)
+    }
+
+    /// Documents containing every term and passing `filter`, best first.
+    pub fn search<F: Fn(&Document) -> bool>(&self, terms: &[String], filter: F) -> Vec<Hit> {
+        if terms.is_empty() || self.documents.is_empty() {
+            return Vec::new();
+        }
+        let count = self.documents.len() as f64;
+        let average_length = self.lengths.values().sum::<f64>() / count;
+
+        let mut postings = Vec::new();
+        for term in terms {
+            match self.postings.get(term) {
+                Some(posting) => postings.push(posting),
+                None => return Vec::new(),
+            }
+        }
+        // walk the rarest term and look the document up in the others
+        postings.sort_by_key(|posting| posting.len());
+
+        let mut hits : Vec<Hit> = postings[0].keys().cloned()
+            .filter(|id| postings[1..].iter().all(|posting| posting.contains_key(id)))
+            .filter(|id| filter(&self.documents[id]))
+            .map(|id| {
+                let length = self.lengths[&id];
+                let score = postings.iter().map(|posting| {
+                    let frequency = posting[&id];
+                    let idf = (1.0 + (count - posting.len() as f64 + 0.5) / (posting.len() as f64 + 0.5)).ln();
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length / average_length))
+                }).sum();
+                Hit { id: id, score: score }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap().then(b.id.cmp(&a.id)));
+        hits
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// HTML-escapes `text[from..to]` and wraps the matching words in `<mark>`.
+fn mark(text: &str, ranges: &[(usize, usize)], terms: &HashSet<String>, from: usize, to: usize) -> String {
+    let mut out = String::new();
+    let mut position = from;
+    for &(start, end) in ranges.iter().filter(|&&(start, end)| start >= from && end <= to) {
+        if terms.contains(&text[start..end].to_lowercase()) {
+            escape_html(&text[position..start], &mut out);
+            out.push_str("<mark>");
+            escape_html(&text[start..end], &mut out);
+            out.push_str("</mark>");
+            position = end;
+        }
+    }
+    escape_html(&text[position..to], &mut out);
+    out
+}
+
+pub fn highlight(text: &str, terms: &HashSet<String>) -> String {
+    mark(text, &words(text), terms, 0, text.len())
+}
+
+/// About `SNIPPET_WORDS` words around the first match, with the matches highlighted.
+pub fn snippet(text: &str, terms: &HashSet<String>) -> String {
+    let ranges = words(text);
+    if ranges.len() <= SNIPPET_WORDS {
+        return highlight(text, terms);
+    }
+    let first_match = ranges.iter()
+        .position(|&(start, end)| terms.contains(&text[start..end].to_lowercase()))
+        .unwrap_or(0);
+    let first = first_match.saturating_sub(SNIPPET_WORDS / 3).min(ranges.len() - SNIPPET_WORDS);
+    let last = first + SNIPPET_WORDS - 1;
+
+    let mut out = String::new();
+    if first > 0 {
+        out.push_str("…");
+    }
+    out.push_str(&mark(text, &ranges, terms, ranges[first].0, ranges[last].1));
+    if last < ranges.len() - 1 {
+        out.push_str("…");
+    }
+    out
+}
+
+fn get_document_from_row( row : tiberius::query::QueryRow ) -> Option<Document> {
+    let id : i32 = row.get(0);
+    let slug : &str = row.get(1);
+    let title : &str = row.get(2);
+    let description : &str = row.get(3);
+    let body : &str = row.get(4);
+    let author : &str = row.get(5);
+    let tags : Option<&str> = row.get(6);
+    Some(Document {
+        id: id,
+        slug: slug.to_string(),
+        title: title.to_string(),
+        description: description.to_string(),
+        body: body.to_string(),
+        author: author.to_string(),
+        tags: tags.map(|tags| tags.split(",").map(|t| t.to_string()).collect()).unwrap_or_else(Vec::new),
+    })
+}
+
+fn fetch_all_documents() -> Vec<Document> {
+    fetch_rows("declare @slug nvarchar(250) = @P1", DOCUMENT_SELECT, get_document_from_row, &[&""])
+}
+
+/// Fills the index from the database in the background, searches see the articles once it is done.
+/// A failed read (e.g. the database not being up yet) is logged and retried after `LOAD_RETRY_SECS`,
+/// until shutdown starts.
+pub fn load() -> thread::JoinHandle<()> {
+    INDEX.write().unwrap().start_loading();
+    thread::spawn(|| loop {
+        match thread::spawn(fetch_all_documents).join() {
+            Ok(documents) => {
+                let count = INDEX.write().unwrap().finish_loading(documents);
+                println!("Search index loaded {} articles", count);
+                return;
+            }
+            Err(_) => {
+                println!("Search index load failed, retrying in {} seconds", LOAD_RETRY_SECS);
+                if !shutdown::sleep(Duration::from_secs(LOAD_RETRY_SECS)) {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Re-reads the articles with these slugs after they were written; slugs that no longer exist are dropped.
+pub fn refresh(slugs: &[&str]) {
+    // a refresh that panicked on the database left nothing half applied
+    let _refreshing = REFRESHING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for slug in slugs.iter().filter(|slug| !slug.is_empty()) {
+        let documents = fetch_rows("declare @slug nvarchar(250) = @P1", DOCUMENT_SELECT, get_document_from_row, &[slug]);
+        let mut index = INDEX.write().unwrap();
+        index.touch(slug);
+        index.remove_slug(slug);
+        for document in documents {
+            index.upsert(document);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct Highlights {
+    title: String,
+    description: String,
+    body: String,
+    tagList: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct SearchResult {
+    article: Article,
+    score: f64,
+    highlights: Highlights,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct SearchResults {
+    results: Vec<SearchResult>,
+    resultsCount: i32,
+}
+
+fn get_ranked_article_from_row( row : tiberius::query::QueryRow ) -> Option<(i32, Article)> {
+    let id : i32 = row.get(16);
+    get_simple_article_from_row(row).map(|article| (id, article))
+}
+
+/// `GET /api/search?q=`, optionally narrowed by `tag` and `author` (both repeatable) and paged with `limit`/`offset`.
+pub fn search_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let query = p.query();
+    println!("search_handler query:'{:?}'", query);
+
+    let terms = tokenize(query.get("q").unwrap_or(""));
+    if terms.is_empty() {
+        return send_error(res, StatusCode::UnprocessableEntity, "q must contain at least one word");
+    }
+    let page = match query.page() {
+        Ok(page) => page,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let tags : Vec<String> = query.get_all("tag").iter().map(|t| t.to_lowercase()).collect();
+    let authors = query.get_all("author");
+
+    let term_set : HashSet<String> = terms.iter().cloned().collect();
+    let (ranked, count) = {
+        let index = INDEX.read().unwrap();
+        let hits = index.search(&terms, |document| {
+            (tags.is_empty() || document.tags.iter().any(|t| tags.contains(&t.to_lowercase())))
+                && (authors.is_empty() || authors.contains(&document.author.as_str()))
+        });
+        let count = hits.len() as i32;
+        let ranked : Vec<(Hit, Highlights)> = hits.into_iter()
+            .skip(page.offset as usize)
+            .take(page.limit as usize)
+            .map(|hit| {
+                let document = index.get(hit.id).unwrap();
+                let highlights = Highlights {
+                    title: highlight(&document.title, &term_set),
+                    description: highlight(&document.description, &term_set),
+                    body: snippet(&document.body, &term_set),
+                    tagList: document.tags.iter().map(|tag| highlight(tag, &term_set)).collect(),
+                };
+                (hit, highlights)
+            })
+            .collect();
+        (ranked, count)
+    };
+
+    let ids : Vec<i32> = ranked.iter().map(|&(ref hit, _)| hit.id).collect();
+    let ids = serde_json::to_string(&ids).unwrap();
+    let mut articles : HashMap<i32, Article> = fetch_rows(
+        r#"declare @logged int = @p1;
+        declare @ids nvarchar(max) = @p2;"#,
+        r#"SELECT Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image],
+                (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
+                (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount,
+                (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
+				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags,
+                Articles.[CurrentStatus], Articles.PublishedAt, Articles.PublishAt, Articles.Id
+                FROM Articles INNER JOIN Users on Author=Users.Id
+                WHERE Articles.Id IN ( SELECT CAST(value AS int) FROM OPENJSON(@ids) )"#,
+        get_ranked_article_from_row,
+        &[&logged_id, &ids.as_str()]
+    ).into_iter().collect();
+
+    // an article deleted since the index was searched is left out
+    let results = ranked.into_iter()
+        .filter_map(|(hit, highlights)| articles.remove(&hit.id).map(|article| SearchResult {
+            article: article,
+            score: hit.score,
+            highlights: highlights,
+        }))
+        .collect();
+
+    send_json(res, &SearchResults { results: results, resultsCount: count });
+}
+
+#[cfg(test)]
+fn document(id: i32, title: &str, body: &str, tags: &[&str]) -> Document {
+    Document {
+        id: id,
+        slug: format!("article-{}", id),
+        title: title.to_string(),
+        description: String::new(),
+        body: body.to_string(),
+        author: "jake".to_string(),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn index_ranking_test() {
+    let mut index = Index::default();
+    index.upsert(document(1, "Training dragons", "It takes a dragon to train a dragon.", &["dragons"]));
+    index.upsert(document(2, "Cooking", "Dragon fruit is sweet.", &[]));
+    index.upsert(document(3, "Gardening", "Nothing to see here.", &[]));
+
+    let dragon = vec!["dragon".to_string()];
+    let ids : Vec<i32> = index.search(&dragon, |_| true).into_iter().map(|hit| hit.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+
+    let ids : Vec<i32> = index.search(&tokenize("Dragon FRUIT"), |_| true).into_iter().map(|hit| hit.id).collect();
+    assert_eq!(ids, vec![2]);
+
+    assert!(index.search(&dragon, |d| d.tags.is_empty()).iter().all(|hit| hit.id == 2));
+    assert!(index.search(&tokenize("unicorn"), |_| true).is_empty());
+
+    index.upsert(document(2, "Cooking", "Apples are sweet.", &[]));
+    assert_eq!(index.search(&dragon, |_| true).len(), 1);
+    index.remove_slug("article-1");
+    assert!(index.search(&dragon, |_| true).is_empty());
+    assert!(index.postings.get("dragon").is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn finish_loading_test() {
+    let mut index = Index::default();
+    index.start_loading();
+    // written while the load was reading: a newer copy, and one that was unpublished
+    index.upsert(document(1, "Training dragons, second edition", "", &[]));
+    index.touch("article-1");
+    index.touch("article-2");
+
+    let loaded = vec![document(1, "Training dragons", "", &[]), document(2, "Cooking", "", &[]), document(3, "Gardening", "", &[])];
+    assert_eq!(index.finish_loading(loaded), 1);
+    assert_eq!(index.get(1).unwrap().title, "Training dragons, second edition");
+    assert!(index.get(2).is_none());
+    assert!(index.get(3).is_some());
+
+    // once loaded, refreshes are no longer remembered
+    index.touch("article-4");
+    assert!(index.touched.is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn highlight_test() {
+    let terms : HashSet<String> = vec!["dragon".to_string()].into_iter().collect();
+    assert_eq!(highlight("A <Dragon>!", &terms), "A &lt;<mark>Dragon</mark>&gt;!");
+    assert_eq!(highlight("Žluťoučký dragon", &terms), "Žluťoučký <mark>dragon</mark>");
+
+    let body = (0..100).map(|i| if i == 50 { "dragon".to_string() } else { format!("w{}", i) })
+        .collect::<Vec<String>>().join(" ");
+    let snippet = snippet(&body, &terms);
+    assert!(snippet.starts_with("…w40 "));
+    assert!(snippet.contains("<mark>dragon</mark>"));
+    assert!(snippet.ends_with(" w69…"));
+}
+
+#[cfg(test)]
+#[test]
+fn search_articles_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    // the generated titles are unique in their number suffix
+    let unique = slug.rsplit('-').next().unwrap().to_string();
+    let since = slug.split('-').rev().nth(1).unwrap().to_string();
+
+    let mut res = client.get(&format!("http://localhost:6767/api/search?q=train+dragon+{}+{}", since, unique))
+        .header(Authorization(Bearer {token: jwt}))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let results : SearchResults = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(results.resultsCount, 1);
+    assert_eq!(results.results[0].article.slug, slug);
+    assert!(results.results[0].highlights.title.contains("<mark>dragon</mark>"));
+
+    let res = client.get("http://localhost:6767/api/search?q=+")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
@@ -0,0 +1,683 @@
+extern crate iis;
+extern crate toml;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+
+static CONFIG_FILE_NAME : &'static str = r#"conduit.toml"#;
+static ENV_PREFIX : &'static str = "CONDUIT__";
+static LEGACY_CONFIG_VAR : &'static str = "DATABASECONFIG";
+
+static DEFAULT_BIND : &'static str = "127.0.0.1";
+static DEFAULT_PORT : u16 = 6767;
+static DEFAULT_SHUTDOWN_TIMEOUT : u64 = 30;
+static DEFAULT_PUBLISH_INTERVAL : u64 = 30;
+static DEFAULT_CORS_METHODS : &'static [&'static str] = &["GET", "POST", "PUT", "DELETE", "OPTIONS"];
+static DEFAULT_CORS_HEADERS : &'static [&'static str] = &["content-type", "authorization", "if-match"];
+static DEFAULT_CORS_MAX_AGE : u32 = 86400;
+static DEFAULT_RESERVED_SLUGS : &'static [&'static str] = &["feed"];
+/// Length of the `Tags.Tag` column.
+static MAX_TAG_LENGTH : usize = 250;
+
+/// Validated settings, loaded once at startup.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub database: DatabaseSettings,
+    pub server: ServerSettings,
+    /// HTTPS is served when present.
+    pub tls: Option<TlsSettings>,
+    pub cors: CorsSettings,
+    pub pagination: PaginationSettings,
+    pub articles: ArticleSettings,
+    pub tags: TagSettings,
+    pub admin: AdminSettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseSettings {
+    pub connection_string: String,
+    pub database_name: String,
+    /// `/createdb` is refused when not set.
+    pub create_database_secret: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerSettings {
+    pub bind: String,
+    pub port: u16,
+    pub unix_socket: Option<String>,
+    /// Seconds to wait for in-flight requests on shutdown.
+    pub shutdown_timeout: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// PEM file with the certificate chain, re-read on SIGHUP.
+    pub certificate: String,
+    /// PEM file with the private key, re-read on SIGHUP.
+    pub private_key: String,
+    /// Plain HTTP port answering with a redirect to HTTPS.
+    pub redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsSettings {
+    /// `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache a preflight response.
+    pub max_age: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PaginationSettings {
+    /// Key signing the `after`/`before` cursors; a random one is used when not set,
+    /// so cursors handed out stop working after a restart.
+    pub cursor_secret: Option<String>,
+}
+
+/// What is appended when an article's slug is already taken.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlugStrategy {
+    /// `hello-world-2`, `hello-world-3`, ...
+    Counter,
+    /// `hello-world-x7k2qa`
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArticleSettings {
+    pub slug_strategy: SlugStrategy,
+    /// Slugs no article may get because they collide with routes, e.g. `feed`.
+    pub reserved_slugs: Vec<String>,
+    /// Seconds between two runs of the scheduler publishing articles whose `publishAt` has come.
+    pub publish_interval: u64,
+}
+
+/// How tags from a `tagList` are normalized before they are stored or looked up.
+#[derive(Debug, Clone)]
+pub struct TagSettings {
+    pub trim: bool,
+    /// Folds case, so `Rust` and `rust` are one tag.
+    pub lowercase: bool,
+    /// In characters, at most the 250 the column holds.
+    pub max_length: usize,
+    /// Characters allowed besides letters and digits; any character but `,` is allowed when not set.
+    pub allowed_characters: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminSettings {
+    /// Users allowed to merge tags and to add tag aliases.
+    pub usernames: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    database: Option<DatabaseConfig>,
+    server: Option<ServerConfig>,
+    tls: Option<TlsConfig>,
+    cors: Option<CorsConfig>,
+    pagination: Option<PaginationConfig>,
+    articles: Option<ArticlesConfig>,
+    tags: Option<TagsConfig>,
+    admin: Option<AdminConfig>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct DatabaseConfig {
+    connection_string: Option<String>,
+    database_name: Option<String>,
+    create_database_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerConfig {
+    bind: Option<String>,
+    port: Option<u16>,
+    unix_socket: Option<String>,
+    shutdown_timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TlsConfig {
+    certificate: Option<String>,
+    private_key: Option<String>,
+    redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CorsConfig {
+    allowed_origins: Option<Vec<String>>,
+    allowed_methods: Option<Vec<String>>,
+    allowed_headers: Option<Vec<String>>,
+    allow_credentials: Option<bool>,
+    max_age: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PaginationConfig {
+    cursor_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ArticlesConfig {
+    slug_strategy: Option<String>,
+    reserved_slugs: Option<Vec<String>>,
+    publish_interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TagsConfig {
+    trim: Option<bool>,
+    lowercase: Option<bool>,
+    max_length: Option<usize>,
+    allowed_characters: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AdminConfig {
+    usernames: Option<Vec<String>>,
+}
+
+/// Returns the path given by `--config <path>` (or `-c <path>`, `--config=<path>`) as the first argument, if any.
+/// Other arguments belong to whoever launched the server, e.g. IIS, and are ignored.
+fn config_path_from_args(args: &[String]) -> Result<Option<PathBuf>, String> {
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some(arg @ "--config") | Some(arg @ "-c") => match args.get(2) {
+            Some(path) => Ok(Some(PathBuf::from(path))),
+            None => Err(format!("{} expects a file path", arg)),
+        },
+        Some(arg) if arg.starts_with("--config=") => Ok(Some(PathBuf::from(&arg["--config=".len()..]))),
+        _ => Ok(None),
+    }
+}
+
+fn read_config_file(path: &PathBuf, required: bool, legacy: Option<String>) -> Result<Config, String> {
+    let display = path.display();
+    let mut content = legacy.unwrap_or_default();
+
+    if path.exists() {
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|why| format!("couldn't read {}: {}", display, why))?;
+        println!("Using configuration from {}", display);
+    } else if required {
+        return Err(format!("config file {} does not exist", display));
+    } else if content.is_empty() {
+        return Ok(Config::default());
+    }
+
+    toml::from_str(&content).map_err(|why| format!("{}: {}", display, why))
+}
+
+fn parse_env_list(value: &str) -> Vec<String> {
+    value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+}
+
+fn parse_env<T: ::std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("{}: '{}' is not a valid value", name, value))
+}
+
+/// Applies one `CONDUIT__SECTION__KEY=value` variable on top of the file configuration.
+fn apply_env(config: &mut Config, name: &str, value: &str) -> Result<(), String> {
+    let path : Vec<String> = name[ENV_PREFIX.len()..].split("__").map(|p| p.to_lowercase()).collect();
+    if path.len() != 2 {
+        return Err(format!("{}: expected {}SECTION__KEY", name, ENV_PREFIX));
+    }
+    let value = value.to_string();
+
+    match (path[0].as_str(), path[1].as_str()) {
+        ("database", key) => {
+            let database = config.database.get_or_insert_with(DatabaseConfig::default);
+            match key {
+                "connection_string" => database.connection_string = Some(value),
+                "database_name" => database.database_name = Some(value),
+                "create_database_secret" => database.create_database_secret = Some(value),
+                _ => return Err(format!("{}: unknown key '{}' in [database]", name, key)),
+            }
+        }
+        ("server", key) => {
+            let server = config.server.get_or_insert_with(ServerConfig::default);
+            match key {
+                "bind" => server.bind = Some(value),
+                "port" => server.port = Some(parse_env(name, &value)?),
+                "unix_socket" => server.unix_socket = Some(value),
+                "shutdown_timeout" => server.shutdown_timeout = Some(parse_env(name, &value)?),
+                _ => return Err(format!("{}: unknown key '{}' in [server]", name, key)),
+            }
+        }
+        ("tls", key) => {
+            let tls = config.tls.get_or_insert_with(TlsConfig::default);
+            match key {
+                "certificate" => tls.certificate = Some(value),
+                "private_key" => tls.private_key = Some(value),
+                "redirect_port" => tls.redirect_port = Some(parse_env(name, &value)?),
+                _ => return Err(format!("{}: unknown key '{}' in [tls]", name, key)),
+            }
+        }
+        ("cors", key) => {
+            let cors = config.cors.get_or_insert_with(CorsConfig::default);
+            match key {
+                "allowed_origins" => cors.allowed_origins = Some(parse_env_list(&value)),
+                "allowed_methods" => cors.allowed_methods = Some(parse_env_list(&value)),
+                "allowed_headers" => cors.allowed_headers = Some(parse_env_list(&value)),
+                "allow_credentials" => cors.allow_credentials = Some(parse_env(name, &value)?),
+                "max_age" => cors.max_age = Some(parse_env(name, &value)?),
+                _ => return Err(format!("{}: unknown key '{}' in [cors]", name, key)),
+            }
+        }
+        ("pagination", key) => {
+            let pagination = config.pagination.get_or_insert_with(PaginationConfig::default);
+            match key {
+                "cursor_secret" => pagination.cursor_secret = Some(value),
+                _ => return Err(format!("{}: unknown key '{}' in [pagination]", name, key)),
+            }
+        }
+        ("articles", key) => {
+            let articles = config.articles.get_or_insert_with(ArticlesConfig::default);
+            match key {
+                "slug_strategy" => articles.slug_strategy = Some(value),
+                "reserved_slugs" => articles.reserved_slugs = Some(parse_env_list(&value)),
+                "publish_interval" => articles.publish_interval = Some(parse_env(name, &value)?),
+                _ => return Err(format!("{}: unknown key '{}' in [articles]", name, key)),
+            }
+        }
+        ("tags", key) => {
+            let tags = config.tags.get_or_insert_with(TagsConfig::default);
+            match key {
+                "trim" => tags.trim = Some(parse_env(name, &value)?),
+                "lowercase" => tags.lowercase = Some(parse_env(name, &value)?),
+                "max_length" => tags.max_length = Some(parse_env(name, &value)?),
+                "allowed_characters" => tags.allowed_characters = Some(value),
+                _ => return Err(format!("{}: unknown key '{}' in [tags]", name, key)),
+            }
+        }
+        ("admin", key) => {
+            let admin = config.admin.get_or_insert_with(AdminConfig::default);
+            match key {
+                "usernames" => admin.usernames = Some(parse_env_list(&value)),
+                _ => return Err(format!("{}: unknown key '{}' in [admin]", name, key)),
+            }
+        }
+        (section, _) => return Err(format!("{}: unknown section '{}'", name, section)),
+    }
+    Ok(())
+}
+
+fn validate(config: Config, iis_port: Option<u16>) -> Result<Settings, Vec<String>> {
+    let mut errors = Vec::new();
+
+    let database = config.database.unwrap_or_default();
+    let server = config.server.unwrap_or_default();
+
+    let connection_string = database.connection_string.unwrap_or_default();
+    if connection_string.trim().is_empty() {
+        errors.push("[database] connection_string is required".to_string());
+    }
+    let database_name = database.database_name.unwrap_or_default();
+    if database_name.trim().is_empty() {
+        errors.push("[database] database_name is required".to_string());
+    }
+    let create_database_secret = database.create_database_secret.filter(|s| !s.is_empty());
+
+    let bind = server.bind.unwrap_or_else(|| DEFAULT_BIND.to_string());
+    if server.unix_socket.is_none() && bind.parse::<::std::net::IpAddr>().is_err() {
+        errors.push(format!("[server] bind '{}' is not an IP address", bind));
+    }
+    // behind IIS HttpPlatformHandler the port is not ours to choose
+    let port = iis_port.or(server.port).unwrap_or(DEFAULT_PORT);
+    if port == 0 {
+        errors.push("[server] port must not be 0".to_string());
+    }
+    if let Some(ref unix_socket) = server.unix_socket {
+        if cfg!(not(unix)) {
+            errors.push(format!("[server] unix_socket '{}' is not supported on this platform", unix_socket));
+        }
+    }
+
+    let tls = match config.tls {
+        Some(TlsConfig { certificate: Some(certificate), private_key: Some(private_key), redirect_port }) => {
+            for file in &[&certificate, &private_key] {
+                if !Path::new(file).exists() {
+                    errors.push(format!("[tls] file {} does not exist", file));
+                }
+            }
+            if cfg!(not(feature = "tls")) {
+                errors.push("[tls] is configured, but the server was built without the tls feature".to_string());
+            }
+            if server.unix_socket.is_some() {
+                errors.push("[tls] can not be combined with [server] unix_socket".to_string());
+            }
+            if redirect_port == Some(port) {
+                errors.push(format!("[tls] redirect_port {} is the same as [server] port", port));
+            }
+            Some(TlsSettings { certificate: certificate, private_key: private_key, redirect_port: redirect_port })
+        }
+        Some(TlsConfig { certificate: None, private_key: None, redirect_port: None }) | None => None,
+        Some(_) => {
+            errors.push("[tls] needs both certificate and private_key".to_string());
+            None
+        }
+    };
+
+    let cors = config.cors.unwrap_or_default();
+    let to_strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let cors = CorsSettings {
+        allowed_origins: cors.allowed_origins.unwrap_or_else(|| vec!["*".to_string()]),
+        allowed_methods: cors.allowed_methods.unwrap_or_else(|| to_strings(DEFAULT_CORS_METHODS))
+            .into_iter().map(|m| m.to_uppercase()).collect(),
+        allowed_headers: cors.allowed_headers.unwrap_or_else(|| to_strings(DEFAULT_CORS_HEADERS))
+            .into_iter().map(|h| h.to_lowercase()).collect(),
+        allow_credentials: cors.allow_credentials.unwrap_or(false),
+        max_age: cors.max_age.unwrap_or(DEFAULT_CORS_MAX_AGE),
+    };
+    if cors.allow_credentials && cors.allowed_origins.iter().any(|o| o == "*") {
+        errors.push("[cors] allow_credentials can not be combined with allowed_origins = [\"*\"]".to_string());
+    }
+    for method in &cors.allowed_methods {
+        if method.is_empty() || !method.chars().all(|c| c.is_ascii_alphabetic()) {
+            errors.push(format!("[cors] '{}' is not an HTTP method", method));
+        }
+    }
+
+    let pagination = config.pagination.unwrap_or_default();
+    let cursor_secret = pagination.cursor_secret.filter(|s| !s.is_empty());
+    if cursor_secret.as_ref().map_or(false, |s| s.len() < 16) {
+        errors.push("[pagination] cursor_secret must be at least 16 characters".to_string());
+    }
+
+    let articles = config.articles.unwrap_or_default();
+    let slug_strategy = match articles.slug_strategy.as_ref().map(|s| s.as_str()).unwrap_or("counter") {
+        "counter" => SlugStrategy::Counter,
+        "random" => SlugStrategy::Random,
+        other => {
+            errors.push(format!("[articles] slug_strategy '{}' is not one of counter, random", other));
+            SlugStrategy::Counter
+        }
+    };
+    // `feed` is always reserved, the router would never reach an article with that slug
+    let mut reserved_slugs = to_strings(DEFAULT_RESERVED_SLUGS);
+    for slug in articles.reserved_slugs.unwrap_or_default() {
+        let slug = slug.to_lowercase();
+        if !reserved_slugs.contains(&slug) {
+            reserved_slugs.push(slug);
+        }
+    }
+    let publish_interval = articles.publish_interval.unwrap_or(DEFAULT_PUBLISH_INTERVAL);
+    if publish_interval == 0 {
+        errors.push("[articles] publish_interval must be at least 1 second".to_string());
+    }
+
+    let tags = config.tags.unwrap_or_default();
+    let max_tag_length = tags.max_length.unwrap_or(MAX_TAG_LENGTH);
+    if max_tag_length == 0 || max_tag_length > MAX_TAG_LENGTH {
+        errors.push(format!("[tags] max_length must be between 1 and {}", MAX_TAG_LENGTH));
+    }
+    // tag lists reach SQL comma separated
+    if tags.allowed_characters.as_ref().map_or(false, |c| c.contains(',')) {
+        errors.push("[tags] allowed_characters can not contain ','".to_string());
+    }
+
+    let admin = config.admin.unwrap_or_default();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(Settings {
+        database: DatabaseSettings {
+            connection_string: connection_string,
+            database_name: database_name,
+            create_database_secret: create_database_secret,
+        },
+        server: ServerSettings {
+            bind: bind,
+            port: port,
+            unix_socket: server.unix_socket,
+            shutdown_timeout: server.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT),
+        },
+        tls: tls,
+        cors: cors,
+        pagination: PaginationSettings { cursor_secret: cursor_secret },
+        articles: ArticleSettings {
+            slug_strategy: slug_strategy,
+            reserved_slugs: reserved_slugs,
+            publish_interval: publish_interval,
+        },
+        tags: TagSettings {
+            trim: tags.trim.unwrap_or(true),
+            lowercase: tags.lowercase.unwrap_or(true),
+            max_length: max_tag_length,
+            allowed_characters: tags.allowed_characters,
+        },
+        admin: AdminSettings { usernames: admin.usernames.unwrap_or_default() },
+    })
+}
+
+fn iis_port() -> Result<Option<u16>, String> {
+    if env::var("HTTP_PLATFORM_PORT").is_err() {
+        return Ok(None);
+    }
+    let port = iis::get_port().to_string();
+    port.parse::<u16>().map(Some).map_err(|_| format!("IIS port '{}' is not a valid port", port))
+}
+
+/// Loads defaults, then the config file, then `CONDUIT__SECTION__KEY` environment variables.
+pub fn load(args: &[String]) -> Result<Settings, Vec<String>> {
+    let explicit_path = config_path_from_args(args).map_err(|e| vec![e])?;
+    let required = explicit_path.is_some();
+    let path = match explicit_path {
+        Some(path) => path,
+        None => {
+            let mut path = env::current_dir().map_err(|e| vec![e.to_string()])?;
+            path.push(CONFIG_FILE_NAME);
+            path
+        }
+    };
+
+    let mut config = read_config_file(&path, required, legacy_config()).map_err(|e| vec![e])?;
+
+    let mut errors = Vec::new();
+    let mut vars : Vec<(String, String)> = env::vars().filter(|&(ref name, _)| name.starts_with(ENV_PREFIX)).collect();
+    vars.sort();
+    for (name, value) in vars {
+        if let Err(e) = apply_env(&mut config, &name, &value) {
+            errors.push(e);
+        }
+    }
+    let iis_port = match iis_port() {
+        Ok(port) => port,
+        Err(e) => { errors.push(e); None }
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    validate(config, iis_port)
+}
+
+/// Loads the settings from the process arguments and environment, exiting with the reasons on failure.
+pub fn load_or_exit() -> Settings {
+    let args : Vec<String> = env::args().collect();
+    match load(&args) {
+        Ok(settings) => settings,
+        Err(errors) => {
+            eprintln!("Invalid configuration:");
+            for error in errors {
+                eprintln!("  {}", error);
+            }
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+fn valid_config() -> Config {
+    toml::from_str(r#"
+        [database]
+        connection_string = "server=tcp:127.0.0.1,1433;integratedSecurity=true;"
+        database_name = "Conduit"
+    "#).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn config_path_from_args_test() {
+    assert_eq!(config_path_from_args(&args(&["server"])).unwrap(), None);
+    assert_eq!(config_path_from_args(&args(&["server", "--config", "a.toml"])).unwrap(), Some(PathBuf::from("a.toml")));
+    assert_eq!(config_path_from_args(&args(&["server", "--config=b.toml"])).unwrap(), Some(PathBuf::from("b.toml")));
+    assert!(config_path_from_args(&args(&["server", "-c"])).is_err());
+    assert_eq!(config_path_from_args(&args(&["server", "--port", "80"])).unwrap(), None);
+}
+
+#[cfg(test)]
+#[test]
+fn legacy_config_test() {
+    let missing = PathBuf::from("no such conduit.toml");
+    let legacy = "[database]&&&database_name = \"Legacy\"".replace("&&&", "\n");
+    let config = read_config_file(&missing, false, Some(legacy)).unwrap();
+    assert_eq!(config.database.unwrap().database_name, Some("Legacy".to_string()));
+    assert!(read_config_file(&missing, false, None).unwrap().database.is_none());
+    assert!(read_config_file(&missing, true, Some("[database]".to_string())).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn env_override_test() {
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__SERVER__PORT", "8080").unwrap();
+    apply_env(&mut config, "CONDUIT__SERVER__BIND", "0.0.0.0").unwrap();
+    apply_env(&mut config, "CONDUIT__DATABASE__DATABASE_NAME", "Other").unwrap();
+    assert!(apply_env(&mut config, "CONDUIT__SERVER__PORT", "eighty").is_err());
+    assert!(apply_env(&mut config, "CONDUIT__SERVER__NOPE", "1").is_err());
+    assert!(apply_env(&mut config, "CONDUIT__PORT", "1").is_err());
+
+    let settings = validate(config, None).unwrap();
+    assert_eq!(settings.server.port, 8080);
+    assert_eq!(settings.server.bind, "0.0.0.0");
+    assert_eq!(settings.database.database_name, "Other");
+    assert_eq!(settings.server.shutdown_timeout, DEFAULT_SHUTDOWN_TIMEOUT);
+}
+
+#[cfg(test)]
+#[test]
+fn validate_test() {
+    let errors = validate(Config::default(), None).unwrap_err();
+    assert_eq!(errors.len(), 2);
+
+    let settings = validate(valid_config(), Some(5000)).unwrap();
+    assert_eq!(settings.server.port, 5000);
+    assert_eq!(settings.database.create_database_secret, None);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__SERVER__BIND", "localhost:80").unwrap();
+    assert!(validate(config, None).is_err());
+
+    assert!(toml::from_str::<Config>("[server]\nprot = 1").is_err());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__TLS__CERTIFICATE", "cert.pem").unwrap();
+    assert!(validate(config, None).is_err());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__CORS__ALLOW_CREDENTIALS", "true").unwrap();
+    assert!(validate(config, None).is_err());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__PAGINATION__CURSOR_SECRET", "short").unwrap();
+    assert!(validate(config, None).is_err());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__ARTICLES__SLUG_STRATEGY", "uuid").unwrap();
+    assert!(validate(config, None).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn article_settings_test() {
+    let settings = validate(valid_config(), None).unwrap();
+    assert_eq!(settings.articles.slug_strategy, SlugStrategy::Counter);
+    assert_eq!(settings.articles.reserved_slugs, vec!["feed"]);
+    assert_eq!(settings.articles.publish_interval, DEFAULT_PUBLISH_INTERVAL);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__ARTICLES__SLUG_STRATEGY", "random").unwrap();
+    apply_env(&mut config, "CONDUIT__ARTICLES__RESERVED_SLUGS", "Search,feed").unwrap();
+    let settings = validate(config, None).unwrap();
+    assert_eq!(settings.articles.slug_strategy, SlugStrategy::Random);
+    assert_eq!(settings.articles.reserved_slugs, vec!["feed", "search"]);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__ARTICLES__PUBLISH_INTERVAL", "5").unwrap();
+    assert_eq!(validate(config, None).unwrap().articles.publish_interval, 5);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__ARTICLES__PUBLISH_INTERVAL", "0").unwrap();
+    assert!(validate(config, None).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn tag_settings_test() {
+    let settings = validate(valid_config(), None).unwrap();
+    assert!(settings.tags.trim && settings.tags.lowercase);
+    assert_eq!(settings.tags.max_length, MAX_TAG_LENGTH);
+    assert_eq!(settings.tags.allowed_characters, None);
+    assert!(settings.admin.usernames.is_empty());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__TAGS__LOWERCASE", "false").unwrap();
+    apply_env(&mut config, "CONDUIT__TAGS__MAX_LENGTH", "20").unwrap();
+    apply_env(&mut config, "CONDUIT__TAGS__ALLOWED_CHARACTERS", "-+#").unwrap();
+    apply_env(&mut config, "CONDUIT__ADMIN__USERNAMES", "jake, jane").unwrap();
+    let settings = validate(config, None).unwrap();
+    assert!(!settings.tags.lowercase);
+    assert_eq!(settings.tags.max_length, 20);
+    assert_eq!(settings.tags.allowed_characters, Some("-+#".to_string()));
+    assert_eq!(settings.admin.usernames, vec!["jake", "jane"]);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__TAGS__MAX_LENGTH", "251").unwrap();
+    assert!(validate(config, None).is_err());
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__TAGS__ALLOWED_CHARACTERS", "-,").unwrap();
+    assert!(validate(config, None).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn cors_settings_test() {
+    let settings = validate(valid_config(), None).unwrap();
+    assert_eq!(settings.cors.allowed_origins, vec!["*"]);
+    assert_eq!(settings.cors.allowed_methods, vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"]);
+
+    let mut config = valid_config();
+    apply_env(&mut config, "CONDUIT__CORS__ALLOWED_ORIGINS", "https://a.example, https://b.example").unwrap();
+    apply_env(&mut config, "CONDUIT__CORS__ALLOWED_METHODS", "get,put").unwrap();
+    apply_env(&mut config, "CONDUIT__CORS__ALLOW_CREDENTIALS", "true").unwrap();
+    let settings = validate(config, None).unwrap();
+    assert_eq!(settings.cors.allowed_origins, vec!["https://a.example", "https://b.example"]);
+    assert_eq!(settings.cors.allowed_methods, vec!["GET", "PUT"]);
+    assert!(settings.cors.allow_credentials);
+}
@@ -0,0 +1,202 @@
+extern crate hyper;
+
+#[cfg(unix)]
+extern crate signal_hook;
+
+#[cfg(windows)]
+extern crate ctrlc;
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper::net::NetworkListener;
+use hyper::server::{Handler, Request, Response};
+use hyper::status::StatusCode;
+use hyper::header::Connection;
+
+static SHUTTING_DOWN : AtomicBool = ATOMIC_BOOL_INIT;
+static IN_FLIGHT : AtomicUsize = ATOMIC_USIZE_INIT;
+/// Acceptor threads that may be blocked in `accept` on the socket.
+static ACCEPTING : AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref CLOSE_LISTENERS : Mutex<Vec<Box<Fn() + Send>>> = Mutex::new(Vec::new());
+}
+
+/// Wraps a listener so shutdown can close it: hyper 0.10's `Listening::close` leaves the acceptor
+/// threads running. Every clone shares one socket, each `accept` uses a temporary copy of it.
+pub struct Closable<L> {
+    listener: Arc<Mutex<Option<L>>>,
+}
+
+impl<L: NetworkListener + Send + 'static> Closable<L> {
+    pub fn new(listener: L) -> Closable<L> {
+        let listener = Arc::new(Mutex::new(Some(listener)));
+        let closing = listener.clone();
+        CLOSE_LISTENERS.lock().unwrap().push(Box::new(move || { closing.lock().unwrap().take(); }));
+        Closable { listener: listener }
+    }
+}
+
+impl<L> Clone for Closable<L> {
+    fn clone(&self) -> Closable<L> {
+        Closable { listener: self.listener.clone() }
+    }
+}
+
+fn park_forever() -> ! {
+    loop {
+        thread::park();
+    }
+}
+
+impl<L: NetworkListener + Send + 'static> NetworkListener for Closable<L> {
+    type Stream = L::Stream;
+
+    fn accept(&mut self) -> hyper::Result<L::Stream> {
+        // count first so stop_accepting() wakes every thread that got past the check
+        ACCEPTING.fetch_add(1, Ordering::SeqCst);
+        let listener = if SHUTTING_DOWN.load(Ordering::SeqCst) { None } else { self.listener.lock().unwrap().clone() };
+        let accepted = listener.map(|mut listener| listener.accept());
+        ACCEPTING.fetch_sub(1, Ordering::SeqCst);
+
+        match accepted {
+            // the stream accepted after shutdown started is dropped, it is most likely our own wake up call
+            Some(accepted) if !SHUTTING_DOWN.load(Ordering::SeqCst) => accepted,
+            _ => park_forever(),
+        }
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        match *self.listener.lock().unwrap() {
+            Some(ref mut listener) => listener.local_addr(),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "listener closed")),
+        }
+    }
+
+    fn set_read_timeout(&mut self, duration: Option<Duration>) {
+        if let Some(ref mut listener) = *self.listener.lock().unwrap() {
+            listener.set_read_timeout(duration);
+        }
+    }
+
+    fn set_write_timeout(&mut self, duration: Option<Duration>) {
+        if let Some(ref mut listener) = *self.listener.lock().unwrap() {
+            listener.set_write_timeout(duration);
+        }
+    }
+}
+
+/// Wraps the router, counting requests in flight and refusing new ones once shutdown started.
+pub struct Draining<H: Handler> {
+    handler: H,
+}
+
+impl<H: Handler> Draining<H> {
+    pub fn new(handler: H) -> Draining<H> {
+        Draining { handler: handler }
+    }
+}
+
+struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<H: Handler> Handler for Draining<H> {
+    fn handle<'a, 'k>(&'a self, req: Request<'a, 'k>, mut res: Response<'a>) {
+        // count first so drain() never sees zero while a request is past the check
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        let _in_flight = InFlight;
+
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            *res.status_mut() = StatusCode::ServiceUnavailable;
+            res.headers_mut().set(Connection::close());
+            return;
+        }
+
+        self.handler.handle(req, res);
+    }
+}
+
+#[cfg(unix)]
+pub fn wait_for_signal() {
+    use signal_hook::iterator::Signals;
+
+    let signals = Signals::new(&[signal_hook::SIGTERM, signal_hook::SIGINT]).unwrap();
+    for signal in signals.forever() {
+        println!("Received signal {}, shutting down", signal);
+        break;
+    }
+}
+
+#[cfg(windows)]
+pub fn wait_for_signal() {
+    use std::sync::mpsc::channel;
+
+    let (tx, rx) = channel();
+    ctrlc::set_handler(move || { let _ = tx.send(()); }).unwrap();
+    rx.recv().unwrap();
+    println!("Received Ctrl-C, shutting down");
+}
+
+/// Closes the `Closable` listeners so new connections are refused. The acceptor threads blocked in
+/// `accept` still hold the socket; `wake` connects to it once, which lets one of them return and stop.
+pub fn stop_accepting<F: Fn()>(wake: F) {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    for close in CLOSE_LISTENERS.lock().unwrap().iter() {
+        close();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while ACCEPTING.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        wake();
+        thread::sleep(Duration::from_millis(10));
+    }
+    println!("Stopped accepting connections");
+}
+
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `duration`, returning false early once shutdown started, so background threads can end.
+pub fn sleep(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while !shutting_down() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+    false
+}
+
+/// Refuses the requests still arriving on open connections and waits up to `timeout` for the ones in flight.
+/// Database connections are opened per request, so they are closed as the handlers finish.
+/// Returns false when the deadline passed with requests still running.
+pub fn drain(timeout: Duration) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let in_flight = IN_FLIGHT.load(Ordering::SeqCst);
+        if in_flight == 0 {
+            println!("All requests finished");
+            return true;
+        }
+        if Instant::now() >= deadline {
+            println!("Shutdown timeout elapsed with {} requests in flight", in_flight);
+            return false;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
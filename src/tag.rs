@@ -0,0 +1,525 @@
+extern crate hyper;
+extern crate serde_json;
+extern crate tiberius;
+
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+
+use router::Params;
+use settings::TagSettings;
+
+use super::*;
+
+fn allowed_character(settings: &TagSettings, c: char) -> bool {
+    match settings.allowed_characters {
+        Some(ref extra) => c.is_alphanumeric() || extra.contains(c),
+        None => true,
+    }
+}
+
+/// Applies the `[tags]` rules to one tag; the error is meant for a 422 response.
+pub fn normalize(settings: &TagSettings, tag: &str) -> Result<String, String> {
+    let mut tag = if settings.trim { tag.trim().to_string() } else { tag.to_string() };
+    if settings.lowercase {
+        tag = tag.to_lowercase();
+    }
+    if tag.is_empty() {
+        return Err("tags can not be empty".to_string());
+    }
+    if tag.chars().count() > settings.max_length {
+        return Err(format!("tag '{}' is longer than {} characters", tag, settings.max_length));
+    }
+    // tag lists reach SQL comma separated
+    if let Some(c) = tag.chars().find(|c| *c == ',' || !allowed_character(settings, *c)) {
+        return Err(format!("tag '{}' contains '{}', which is not allowed", tag, c));
+    }
+    Ok(tag)
+}
+
+/// Normalizes a `tagList`, skipping blank tags and the ones that became duplicates.
+pub fn normalize_all(settings: &TagSettings, tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized : Vec<String> = Vec::new();
+    for tag in tags.iter().filter(|tag| !tag.trim().is_empty()) {
+        let tag = normalize(settings, tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Tags to filter by; ones that could never have been stored are kept as given and match nothing.
+pub fn normalize_filter(settings: &TagSettings, tags: &[String]) -> Vec<String> {
+    tags.iter().map(|tag| normalize(settings, tag).unwrap_or_else(|_| tag.to_string())).collect()
+}
+
+static RECENT_ARTICLES : i32 = 5;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+struct TagDetail {
+    name: String,
+    description: Option<String>,
+    articlesCount: i32,
+    aliases: Vec<String>,
+    /// Whether the logged in user follows the tag.
+    following: bool,
+    /// Only for `GET /api/tags/:tag`.
+    #[serde(skip_serializing_if="Option::is_none", default)]
+    recentArticles: Option<Vec<Article>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct TagResult {
+    tag: TagDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct MergeTagsDetail {
+    from: String,
+    into: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct MergeTags {
+    merge: MergeTagsDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddAliasDetail {
+    alias: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddAlias {
+    alias: AddAliasDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct UpdateTagDetail {
+    description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct UpdateTag {
+    tag: UpdateTagDetail,
+}
+
+static TAG_DETAIL_SELECT : &'static str = r#"
+  SELECT Tag, [Description], (SELECT COUNT(*) FROM ArticleTags INNER JOIN Articles ON Articles.Id = ArticleTags.ArticleId
+      WHERE TagId = Tags.Id AND Articles.[CurrentStatus] = 'published') AS ArticlesCount,
+  (SELECT STRING_AGG(Alias, ',') FROM TagAliases WHERE TagId = Tags.Id) AS Aliases,
+  (SELECT COUNT(*) FROM TagFollowings WHERE TagId = Tags.Id AND UserId = @logged) AS [Following]
+  FROM Tags WHERE Id = @tagId
+"#;
+
+static RECENT_ARTICLES_SELECT : &'static str = r#"
+  SELECT TOP (@recent) Slug, Title, [Description], Body, Created, Updated, Users.UserName, Users.Bio, Users.[Image], 
+                (SELECT COUNT(*) FROM Followings WHERE FollowerId=@logged AND Author=FollowingId) as [Following],
+                (SELECT COUNT(*) FROM FavoritedArticles WHERE ArticleId = Articles.Id ) as FavoritesCount,
+                (SELECT COUNT(*) FROM FavoritedArticles WHERE UserId = @logged ) as PersonalFavoritesCount,
+				(SELECT STRING_AGG(Tag, ',') FROM [Tags] inner join ArticleTags on ArticleTags.TagId = Tags.Id where ArticleId=Articles.Id)  as Tags,
+                Articles.[CurrentStatus], Articles.PublishedAt, Articles.PublishAt
+                FROM Articles INNER JOIN Users on Author=Users.Id
+                WHERE Articles.Id IN (SELECT ArticleId FROM ArticleTags WHERE TagId = @tagId) AND Articles.[CurrentStatus] = 'published'
+                ORDER BY Articles.Id DESC
+"#;
+
+fn get_tag_detail_from_row( row : tiberius::query::QueryRow ) -> Option<TagResult> {
+    let name : &str = row.get(0);
+    let description : Option<&str> = row.get(1);
+    let aliases : Option<&str> = row.get(3);
+    let following : i32 = row.get(4);
+    Some(TagResult{ tag: TagDetail{
+        name: name.to_string(),
+        description: description.map(|d| d.to_string()),
+        articlesCount: row.get(2),
+        aliases: aliases.map(|aliases| aliases.split(",").map(|a| a.to_string()).collect()).unwrap_or_else(Vec::new),
+        following: following == 1,
+        recentArticles: None,
+    }})
+}
+
+fn get_id_from_row( row : tiberius::query::QueryRow ) -> Option<i32> {
+    Some(row.get(0))
+}
+
+fn get_slug_from_row( row : tiberius::query::QueryRow ) -> Option<String> {
+    let slug : &str = row.get(0);
+    Some(slug.to_string())
+}
+
+/// The tag spelled `name` once normalized, aliases are not followed.
+fn tag_id(name: &str) -> Option<i32> {
+    let name = normalize(&SETTINGS.tags, name).unwrap_or_else(|_| name.to_string());
+    let name : &str = &name;
+    fetch_rows("DECLARE @tag nvarchar(250) = @P1;", "SELECT TOP(1) Id FROM Tags WHERE Tag = @tag", get_id_from_row, &[&name]).pop()
+}
+
+/// The tag `name` stands for, following aliases.
+fn canonical_tag_id(name: &str) -> Option<i32> {
+    let name = normalize(&SETTINGS.tags, name).unwrap_or_else(|_| name.to_string());
+    let name : &str = &name;
+    fetch_rows(
+        "DECLARE @tag nvarchar(250) = dbo.CanonicalTag(@P1);",
+        "SELECT TOP(1) Id FROM Tags WHERE Tag = @tag",
+        get_id_from_row,
+        &[&name]
+    ).pop()
+}
+
+/// `GET /api/tags/:tag`: the tag with its description, usage count, aliases and latest articles.
+pub fn get_tag_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let name = p.get("tag");
+    println!("get_tag_handler tag: '{}'", name);
+
+    let tag_id = match canonical_tag_id(name) {
+        Some(tag_id) => tag_id,
+        None => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", name)),
+    };
+    let mut result = match fetch_rows("DECLARE @tagId int = @P1; DECLARE @logged int = @P2;", TAG_DETAIL_SELECT, get_tag_detail_from_row, &[&tag_id, &logged_id]).pop() {
+        Some(result) => result,
+        None => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", name)),
+    };
+    result.tag.recentArticles = Some(fetch_rows(
+        "DECLARE @tagId int = @P1; DECLARE @logged int = @P2; DECLARE @recent int = @P3;",
+        RECENT_ARTICLES_SELECT,
+        get_simple_article_from_row,
+        &[&tag_id, &logged_id, &RECENT_ARTICLES]
+    ));
+    send_json(res, &result);
+}
+
+/// Answers 401/403 unless the logged in user is one of `[admin] usernames`.
+fn check_admin(logged_id: i32) -> Result<(), (StatusCode, &'static str)> {
+    if logged_id == 0 {
+        return Err((StatusCode::Unauthorized, "login required"));
+    }
+    let admins = serde_json::to_string(&SETTINGS.admin.usernames).unwrap();
+    let found = fetch_rows(
+        "DECLARE @logged int = @P1; DECLARE @admins nvarchar(max) = @P2;",
+        "SELECT Id FROM Users WHERE Id = @logged AND UserName IN ( SELECT value FROM OPENJSON(@admins) )",
+        get_id_from_row,
+        &[&logged_id, &admins.as_str()]
+    );
+    if found.is_empty() {
+        return Err((StatusCode::Forbidden, "only administrators can manage tags"));
+    }
+    Ok(())
+}
+
+/// `POST /api/admin/tags/merge`: moves every article of `from` to `into`; `from` and its aliases become aliases of `into`.
+pub fn merge_tags_handler(req: Request, res: Response, _: Params) {
+    let (body, logged_id) = prepare_parameters(req);
+    if let Err((status, message)) = check_admin(logged_id) {
+        return send_error(res, status, message);
+    }
+    let merge : MergeTags = match serde_json::from_str(&body) {
+        Ok(merge) => merge,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e.to_string()),
+    };
+    let (from, into) = match (tag_id(&merge.merge.from), tag_id(&merge.merge.into)) {
+        (Some(from), Some(into)) => (from, into),
+        (None, _) => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", merge.merge.from)),
+        (_, None) => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", merge.merge.into)),
+    };
+    if from == into {
+        return send_error(res, StatusCode::UnprocessableEntity, "a tag can not be merged into itself");
+    }
+    println!("merge_tags_handler {} into {}", merge.merge.from, merge.merge.into);
+
+    let slugs = fetch_rows(
+        "DECLARE @from int = @P1;",
+        "SELECT Slug FROM Articles WHERE Id IN (SELECT ArticleId FROM ArticleTags WHERE TagId = @from)",
+        get_slug_from_row,
+        &[&from]
+    );
+    process_with(
+        res,
+        r#"DECLARE @from int = @P1; DECLARE @tagId int = @P2; DECLARE @logged int = @P3;
+        BEGIN TRANSACTION;
+        INSERT INTO ArticleTags (ArticleId, TagId) SELECT DISTINCT ArticleId, @tagId FROM ArticleTags
+            WHERE TagId = @from AND ArticleId NOT IN (SELECT ArticleId FROM ArticleTags WHERE TagId = @tagId);
+        DELETE FROM ArticleTags WHERE TagId = @from;
+        INSERT INTO TagFollowings (UserId, TagId) SELECT UserId, @tagId FROM TagFollowings
+            WHERE TagId = @from AND UserId NOT IN (SELECT UserId FROM TagFollowings WHERE TagId = @tagId);
+        DELETE FROM TagFollowings WHERE TagId = @from;
+        UPDATE TagAliases SET TagId = @tagId WHERE TagId = @from;
+        INSERT INTO TagAliases (Alias, TagId) SELECT Tag, @tagId FROM Tags WHERE Id = @from AND Tag NOT IN (SELECT Alias FROM TagAliases);
+        DELETE FROM Tags WHERE Id = @from;
+        COMMIT;
+        "#,
+        TAG_DETAIL_SELECT,
+        get_tag_detail_from_row,
+        &[&from, &into, &logged_id],
+        |_: &TagResult| search::refresh(&slugs.iter().map(|slug| slug.as_str()).collect::<Vec<&str>>())
+    );
+}
+
+/// `POST /api/admin/tags/aliases`: makes `alias` stand for `tag` from now on; a tag in use has to be merged instead.
+pub fn add_tag_alias_handler(req: Request, res: Response, _: Params) {
+    let (body, logged_id) = prepare_parameters(req);
+    if let Err((status, message)) = check_admin(logged_id) {
+        return send_error(res, status, message);
+    }
+    let add : AddAlias = match serde_json::from_str(&body) {
+        Ok(add) => add,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e.to_string()),
+    };
+    let alias = match normalize(&SETTINGS.tags, &add.alias.alias) {
+        Ok(alias) => alias,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let tag = match tag_id(&add.alias.tag) {
+        Some(tag) => tag,
+        None => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", add.alias.tag)),
+    };
+    if tag_id(&alias).is_some() {
+        return send_error(res, StatusCode::UnprocessableEntity, &format!("'{}' is a tag, merge it instead", alias));
+    }
+    println!("add_tag_alias_handler {} for {}", alias, add.alias.tag);
+
+    process(
+        res,
+        r#"DECLARE @alias nvarchar(250) = @P1; DECLARE @tagId int = @P2; DECLARE @logged int = @P3;
+        DELETE FROM TagAliases WHERE Alias = @alias;
+        INSERT INTO TagAliases (Alias, TagId) VALUES (@alias, @tagId);
+        "#,
+        TAG_DETAIL_SELECT,
+        get_tag_detail_from_row,
+        &[&alias.as_str(), &tag, &logged_id]
+    );
+}
+
+/// `PUT /api/admin/tags/:tag`: sets the description shown by `GET /api/tags/:tag`.
+pub fn update_tag_handler(req: Request, res: Response, p: Params) {
+    let (body, logged_id) = prepare_parameters(req);
+    if let Err((status, message)) = check_admin(logged_id) {
+        return send_error(res, status, message);
+    }
+    let update : UpdateTag = match serde_json::from_str(&body) {
+        Ok(update) => update,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e.to_string()),
+    };
+    let name = p.get("tag");
+    let tag_id = match canonical_tag_id(name) {
+        Some(tag_id) => tag_id,
+        None => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", name)),
+    };
+    let description : &str = update.tag.description.as_ref().map(|d| d.trim()).unwrap_or("");
+    if description.chars().count() > 1000 {
+        return send_error(res, StatusCode::UnprocessableEntity, "description is longer than 1000 characters");
+    }
+    println!("update_tag_handler tag: '{}'", name);
+
+    process(
+        res,
+        "DECLARE @tagId int = @P1; DECLARE @logged int = @P3; UPDATE Tags SET [Description] = NULLIF(@P2, '') WHERE Id = @tagId;",
+        TAG_DETAIL_SELECT,
+        get_tag_detail_from_row,
+        &[&tag_id, &description, &logged_id]
+    );
+}
+
+/// `POST /api/tags/:tag/follow`: articles with the tag show up in the feed; the tag is created when nobody used it yet.
+pub fn follow_tag_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+    if logged_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required");
+    }
+    let name = match normalize(&SETTINGS.tags, p.get("tag")) {
+        Ok(name) => name,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let name : &str = &name;
+    println!("follow_tag_handler tag: '{}'", name);
+
+    process(
+        res,
+        r#"DECLARE @tag nvarchar(250) = dbo.CanonicalTag(@P1); DECLARE @logged int = @P2;
+        INSERT INTO Tags (Tag) SELECT @tag WHERE NOT EXISTS (SELECT 1 FROM Tags WHERE Tag = @tag);
+        DECLARE @tagId int = (SELECT TOP(1) Id FROM Tags WHERE Tag = @tag);
+        INSERT INTO TagFollowings (UserId, TagId) SELECT @logged, @tagId
+            WHERE NOT EXISTS (SELECT 1 FROM TagFollowings WHERE UserId = @logged AND TagId = @tagId);
+        "#,
+        TAG_DETAIL_SELECT,
+        get_tag_detail_from_row,
+        &[&name, &logged_id]
+    );
+}
+
+pub fn unfollow_tag_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_id) = prepare_parameters(req);
+    if logged_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required");
+    }
+    let name = p.get("tag");
+    let tag_id = match canonical_tag_id(name) {
+        Some(tag_id) => tag_id,
+        None => return send_error(res, StatusCode::NotFound, &format!("tag '{}' not found", name)),
+    };
+    println!("unfollow_tag_handler tag: '{}'", name);
+
+    process(
+        res,
+        "DECLARE @tagId int = @P1; DECLARE @logged int = @P2; DELETE FROM TagFollowings WHERE UserId = @logged AND TagId = @tagId;",
+        TAG_DETAIL_SELECT,
+        get_tag_detail_from_row,
+        &[&tag_id, &logged_id]
+    );
+}
+
+#[cfg(test)]
+use rand::Rng;
+
+#[cfg(test)]
+fn tag_settings(allowed_characters: Option<&str>) -> TagSettings {
+    TagSettings { trim: true, lowercase: true, max_length: 10, allowed_characters: allowed_characters.map(|c| c.to_string()) }
+}
+
+#[cfg(test)]
+#[test]
+fn normalize_test() {
+    let settings = tag_settings(Some("-+#"));
+    assert_eq!(normalize(&settings, " Rust "), Ok("rust".to_string()));
+    assert_eq!(normalize(&settings, "C++"), Ok("c++".to_string()));
+    assert_eq!(normalize(&settings, "Über"), Ok("über".to_string()));
+    assert!(normalize(&settings, "  ").is_err());
+    assert!(normalize(&settings, "rust lang").is_err());
+    assert!(normalize(&settings, "elevenchars").is_err());
+
+    let any = tag_settings(None);
+    assert_eq!(normalize(&any, "rust lang"), Ok("rust lang".to_string()));
+    assert!(normalize(&any, "a,b").is_err());
+
+    let tags = vec!["Rust".to_string(), " rust".to_string(), "".to_string(), "Go".to_string()];
+    assert_eq!(normalize_all(&settings, &tags), Ok(vec!["rust".to_string(), "go".to_string()]));
+    assert_eq!(normalize_filter(&settings, &["rust lang".to_string(), "Go ".to_string()]), vec!["rust lang", "go"]);
+}
+
+#[cfg(test)]
+#[test]
+fn normalized_tags_test() {
+    let client = Client::new();
+
+    let (jwt, _, _) = login_create_article(false);
+    let body = r#"{"article": {"title": "Tag case", "description": "Ever wonder how?", "body": "Same tag",
+                "tagList": [" Dragons ", "DRAGONS", "dragons", ""]}}"#;
+    let mut res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let created : CreateArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(created.article.tagList, vec!["dragons"]);
+
+    let res = client.post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body(r#"{"article": {"title": "Bad tag", "description": "d", "body": "b", "tagList": ["a,b"]}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::UnprocessableEntity);
+
+    // the test users are never administrators
+    let res = client.post("http://localhost:6767/api/admin/tags/merge")
+        .header(Authorization(Bearer {token: jwt}))
+        .body(r#"{"merge": {"from": "angularjs", "into": "reactjs"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::Forbidden);
+}
+
+#[cfg(test)]
+#[test]
+fn get_tag_test() {
+    let client = Client::new();
+    login_create_article(false);
+
+    let mut res = client.get("http://localhost:6767/api/tags/Dragons")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result : TagResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.tag.name, "dragons");
+    assert!(result.tag.articlesCount >= 1);
+    let recent = result.tag.recentArticles.unwrap();
+    assert!(recent.len() <= RECENT_ARTICLES as usize);
+    assert!(recent.iter().all(|article| article.tagList.iter().any(|tag| tag == "dragons")));
+
+    let res = client.get("http://localhost:6767/api/tags/no-such-tag-ever")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[cfg(test)]
+fn follow_tag(jwt: &str, tag: &str, follow: bool) -> TagResult {
+    let client = Client::new();
+    let url = format!("http://localhost:6767/api/tags/{}/follow", tag);
+    let request = if follow { client.post(&url) } else { client.delete(&url) };
+    let mut res = request
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    serde_json::from_str(&buffer).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn followed_tag_feed_test() {
+    let client = Client::new();
+
+    let (author_jwt, _, _) = login_create_article(false);
+    let (jwt, _, _) = login_create_article(false);
+    let tag = format!("followed{}x{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+    let followed = follow_tag(&jwt, &tag, true);
+    assert_eq!(followed.tag.name, tag);
+    assert!(followed.tag.following);
+
+    let slug = create_article_with_tags(&author_jwt, &[&tag, "dragons"]);
+    let feed = |jwt: &str| {
+        let mut res = client.get("http://localhost:6767/api/articles/feed?limit=100")
+            .header(Authorization(Bearer {token: jwt.to_owned()}))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        let feed : ArticlesResult = serde_json::from_str(&buffer).unwrap();
+        feed.articles
+    };
+    let articles = feed(&jwt);
+    let article = articles.iter().find(|article| article.slug == slug).expect("article with a followed tag in the feed");
+    let source = article.source.as_ref().unwrap();
+    assert!(!source.followedAuthor);
+    assert_eq!(source.followedTags, vec![tag.clone()]);
+
+    let unfollowed = follow_tag(&jwt, &tag, false);
+    assert!(!unfollowed.tag.following);
+    assert!(!feed(&jwt).iter().any(|article| article.slug == slug));
+
+    let res = client.post("http://localhost:6767/api/tags/dragons/follow")
+        .body("")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::Unauthorized);
+}
@@ -0,0 +1,159 @@
+extern crate hyper;
+extern crate openssl;
+
+#[cfg(unix)]
+extern crate signal_hook;
+
+use std::io;
+use std::io::prelude::*;
+use std::net::{SocketAddr, Shutdown};
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(unix)]
+use std::thread;
+use std::time::Duration;
+
+use hyper::net::{HttpStream, NetworkStream, SslServer};
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+use hyper::header::{Host, Location};
+use hyper::uri::RequestUri;
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslMethod, SslStream};
+use openssl::x509::X509_FILETYPE_PEM;
+
+use settings::TlsSettings;
+
+fn build_acceptor(certificate: &str, private_key: &str) -> Result<SslAcceptor, ErrorStack> {
+    let mut builder = SslAcceptorBuilder::mozilla_intermediate_raw(SslMethod::tls())?;
+    {
+        let context = builder.builder_mut();
+        context.set_certificate_chain_file(certificate)?;
+        context.set_private_key_file(private_key, X509_FILETYPE_PEM)?;
+        context.check_private_key()?;
+    }
+    Ok(builder.build())
+}
+
+/// TLS acceptor whose certificate can be swapped while the server keeps running.
+#[derive(Clone)]
+pub struct ReloadableTls {
+    acceptor: Arc<RwLock<SslAcceptor>>,
+    certificate: String,
+    private_key: String,
+}
+
+impl ReloadableTls {
+    pub fn new(settings: &TlsSettings) -> Result<ReloadableTls, ErrorStack> {
+        let acceptor = build_acceptor(&settings.certificate, &settings.private_key)?;
+        Ok(ReloadableTls {
+            acceptor: Arc::new(RwLock::new(acceptor)),
+            certificate: settings.certificate.clone(),
+            private_key: settings.private_key.clone(),
+        })
+    }
+
+    /// Re-reads the certificate and key; connections already established keep the old ones.
+    pub fn reload(&self) -> Result<(), ErrorStack> {
+        let acceptor = build_acceptor(&self.certificate, &self.private_key)?;
+        *self.acceptor.write().unwrap() = acceptor;
+        Ok(())
+    }
+}
+
+impl SslServer for ReloadableTls {
+    type Stream = TlsStream;
+
+    fn wrap_server(&self, stream: HttpStream) -> hyper::Result<TlsStream> {
+        let acceptor = self.acceptor.read().unwrap().clone();
+        match acceptor.accept(stream) {
+            Ok(stream) => Ok(TlsStream(Arc::new(Mutex::new(stream)))),
+            Err(e) => Err(hyper::Error::Ssl(Box::new(io::Error::new(io::ErrorKind::Other, e.to_string())))),
+        }
+    }
+}
+
+/// hyper needs cloneable streams, `SslStream` is not.
+#[derive(Clone)]
+pub struct TlsStream(Arc<Mutex<SslStream<HttpStream>>>);
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+impl NetworkStream for TlsStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.0.lock().unwrap().get_mut().peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.0.lock().unwrap().get_ref().set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.0.lock().unwrap().get_ref().set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.0.lock().unwrap().get_mut().close(how)
+    }
+}
+
+#[cfg(unix)]
+pub fn reload_on_sighup(tls: ReloadableTls) {
+    use signal_hook::iterator::Signals;
+
+    let signals = Signals::new(&[signal_hook::SIGHUP]).unwrap();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            match tls.reload() {
+                Ok(_) => println!("Reloaded TLS certificate {}", tls.certificate),
+                Err(e) => println!("Keeping previous TLS certificate, reload failed: {}", e),
+            }
+        }
+    });
+}
+
+/// There is no SIGHUP on Windows, restart the server to pick up a new certificate.
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_: ReloadableTls) {}
+
+/// Answers plain HTTP requests on `redirect_port` with a permanent redirect to the HTTPS port.
+pub fn redirect_to_https(bind: &str, redirect_port: u16, https_port: u16) -> hyper::server::Listening {
+    let listen_on = format!("{}:{}", bind, redirect_port);
+    println!("Redirecting {} to HTTPS", listen_on);
+
+    hyper::server::Server::http(listen_on).unwrap().handle(move |req: Request, mut res: Response| {
+        let host = match req.headers.get::<Host>() {
+            Some(host) => host.hostname.clone(),
+            None => {
+                *res.status_mut() = StatusCode::BadRequest;
+                return;
+            }
+        };
+        let path = match req.uri {
+            RequestUri::AbsolutePath(ref path) => path.clone(),
+            _ => "/".to_string(),
+        };
+        let location = if https_port == 443 {
+            format!("https://{}{}", host, path)
+        } else {
+            format!("https://{}:{}{}", host, https_port, path)
+        };
+
+        *res.status_mut() = StatusCode::MovedPermanently;
+        res.headers_mut().set(Location(location));
+    }).unwrap()
+}
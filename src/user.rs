@@ -16,8 +16,6 @@ extern crate tiberius;
 
 extern crate toml;
 
-extern crate reroute;
-
 extern crate jwt;
 
 extern crate futures_state_stream;
@@ -31,7 +29,7 @@ use tiberius::stmt::ResultStreamExt;
 use std::io::prelude::*;
 
 use hyper::server::{Request, Response};
-use reroute::{Captures};
+use router::Params;
 use hyper::header::{Authorization, Bearer};
 use hyper::status::StatusCode;
 
@@ -95,6 +93,22 @@ fn get_user_from_row_simple( row : tiberius::query::QueryRow ) -> Option<UserRes
     result
 }
 
+/// A `USER_SELECT` row with the version for its `ETag`.
+fn get_versioned_user_from_row( row : tiberius::query::QueryRow ) -> Option<(i64, UserResult)> {
+    let version : i64 = row.get(6);
+    get_user_from_row_simple(row).map(|user| (version, user))
+}
+
+/// Whether the `If-Match` of a write held.
+fn get_write_outcome_from_row( row : tiberius::query::QueryRow ) -> Option<bool> {
+    let matched : i32 = row.get(0);
+    Some(matched == 1)
+}
+
+fn fetch_user(user_id: i32) -> Option<(i64, UserResult)> {
+    fetch_rows("DECLARE @id int = @P1;", USER_SELECT, get_versioned_user_from_row, &[&user_id]).pop()
+}
+
 fn get_profile_from_row(row : tiberius::query::QueryRow) ->Option<ProfileResult> {
     let _ : &str = row.get(0);
     let _ : &str = row.get(1);
@@ -110,12 +124,12 @@ fn get_profile_from_row(row : tiberius::query::QueryRow) ->Option<ProfileResult>
     result
 }
 
-static USER_SELECT : &'static str = r#"SELECT [Email],[Token],[UserName],[Bio],[Image], Id FROM [dbo].[Users] WHERE [Id] = @id"#;
+static USER_SELECT : &'static str = r#"SELECT [Email],[Token],[UserName],[Bio],[Image], Id, CONVERT(bigint, [Version]) FROM [dbo].[Users] WHERE [Id] = @id"#;
 static PROFILE_SELECT : &'static str = r#"SELECT [Email],[Token],[UserName],[Bio],[Image] ,
 ( SELECT COUNT(*) FROM dbo.Followings F WHERE F.[FollowingId] = Id AND F.FollowerId = @logged ) as Following
 FROM [dbo].[Users]  WHERE [UserName] = @username"#;
 
-pub fn registration_handler(req: Request, res: Response, _: Captures) {
+pub fn registration_handler(req: Request, res: Response, _: Params) {
     let (body, _) = prepare_parameters(req);
 
     let registration : Registration = serde_json::from_str(&body).unwrap();     
@@ -139,8 +153,14 @@ pub fn registration_handler(req: Request, res: Response, _: Captures) {
     );
 }
 
-pub fn update_user_handler(req: Request, res: Response, _: Captures) {
+/// Honors `If-Match` with the user's `ETag`, a write over a newer version is answered with 412.
+pub fn update_user_handler(req: Request, res: Response, _: Params) {
+    let (check_version, versions) = conditional::if_match(&req.headers);
+    let versions : &str = &versions;
     let (body, logged_in_user_id) = prepare_parameters(req);
+    if logged_in_user_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required");
+    }
 
     let update_user : UpdateUser = serde_json::from_str(&body).unwrap();     
     let user_name : &str = &update_user.user.username.as_ref().map(|x| &**x).unwrap_or("");
@@ -150,68 +170,123 @@ pub fn update_user_handler(req: Request, res: Response, _: Captures) {
     let password : &str = &update_user.user.password.as_ref().map(|x| &**x).unwrap_or("");
     let token : &str = &crypto::pbkdf2::pbkdf2_simple(password, 10000).unwrap();
 
-    process(
-        res,
-        r#"  UPDATE [dbo].[Users] SET 
+    let outcome = fetch_rows(
+        r#"  DECLARE @id int = @P1;
+                                BEGIN TRANSACTION;
+                                DECLARE @matched int = (SELECT COUNT(*) FROM [dbo].[Users] WITH (UPDLOCK, HOLDLOCK) WHERE [Id] = @id
+                                    AND ( @P8 = 0 OR CONVERT(nvarchar(20), CONVERT(bigint, [Version])) IN (SELECT Item FROM dbo.SplitNVarchars(@P9, ',')) ));
+                                UPDATE [dbo].[Users] SET 
                                 [UserName]=CASE WHEN(LEN(@P2)=0) THEN UserName ELSE @P2 END,
                                 [Bio]=CASE WHEN(LEN(@P3)=0) THEN Bio ELSE @P3 END,
                                 [Image]=CASE WHEN(LEN(@P4)=0) THEN Image ELSE @P4 END,
                                 [Email]=CASE WHEN(LEN(@P5)=0) THEN Email ELSE @P5 END,
                                 [Token]=CASE WHEN(LEN(@P7)=0) THEN Token ELSE @P6 END
-                                WHERE [Id] = @P1; DECLARE @id int = @P1;
-                            "#, USER_SELECT,
-        get_user_from_row_simple,
-        &[&logged_in_user_id, &user_name, &bio, &image, &email, &token, &password ]
-    );    
+                                WHERE [Id] = @id AND @matched = 1;
+                                COMMIT;
+                            "#,
+        "SELECT @matched FROM [dbo].[Users] WHERE [Id] = @id",
+        get_write_outcome_from_row,
+        &[&logged_in_user_id, &user_name, &bio, &image, &email, &token, &password, &check_version, &versions ]
+    ).pop();
+    match (outcome, fetch_user(logged_in_user_id)) {
+        (Some(false), Some((version, current))) => conditional::send_precondition_failed(res, version, &current),
+        (Some(true), Some((version, user))) => conditional::send_versioned(res, version, &user),
+        _ => send_error(res, StatusCode::NotFound, "user not found"),
+    }
 }
 
-pub fn get_current_user_handler(req: Request, res: Response, _: Captures) {
+pub fn get_current_user_handler(req: Request, res: Response, _: Params) {
     let (_, logged_in_user_id) = prepare_parameters(req);
+    if logged_in_user_id == 0 {
+        return send_error(res, StatusCode::Unauthorized, "login required");
+    }
 
-    process(
-        res,
-        r#"DECLARE @id int = @P1;"#, USER_SELECT,
-        get_user_from_row_simple,
-        &[&logged_in_user_id]
-    ); 
+    match fetch_user(logged_in_user_id) {
+        Some((version, user)) => conditional::send_versioned(res, version, &user),
+        None => send_error(res, StatusCode::NotFound, "user not found"),
+    }
 }
 
-pub fn get_profile_handler(req: Request, res: Response, c: Captures) {
+pub fn get_profile_handler(req: Request, res: Response, p: Params) {
+    let conditions = conditional::Conditions::from_headers(&req.headers);
     let (_, logged_in_user_id) = prepare_parameters(req);
 
-    let caps = c.unwrap();
-    let profile = &caps[0].replace("/api/profiles/", "");
+    let profile = p.get("username");
     println!("profile: {}", profile);
 
-    process(
-        res,
+    let found = fetch_rows(
         r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;"#, PROFILE_SELECT,
         get_profile_from_row,
-        &[&(profile.as_str()), &logged_in_user_id]
-    ); 
+        &[&profile, &logged_in_user_id]
+    ).pop();
+    match found {
+        Some(found) => conditional::send_cached(res, &conditions, &conditional::PROFILE_CACHE, None, None, &found),
+        None => send_error(res, StatusCode::NotFound, "profile not found"),
+    }
+}
+
+fn get_suggested_profile_from_row(row : tiberius::query::QueryRow) -> Option<Profile> {
+    let user_name : &str = row.get(0);
+    let bio : Option<&str> = row.get(1);
+    let image : Option<&str> = row.get(2);
+    let f : i32 = row.get(3);
+    Some(Profile{ 
+        following:f == 1, bio:bio.map(|s| s.to_string()),
+        image:image.map(|s| s.to_string()), username:user_name.to_string()
+    })
+}
+
+/// `GET /api/profiles?q=` for a typeahead: exact match first, then prefixes, then substrings, then names sounding alike.
+pub fn search_profiles_handler(req: Request, res: Response, p: Params) {
+    let (_, logged_in_user_id) = prepare_parameters(req);
+
+    let q = match p.query().get("q") {
+        Some(q) => q,
+        None => return send_error(res, StatusCode::UnprocessableEntity, "q is required"),
+    };
+    let limit = match p.query().limit(query::DEFAULT_SUGGESTION_LIMIT, query::MAX_SUGGESTION_LIMIT) {
+        Ok(limit) => limit,
+        Err(e) => return send_error(res, StatusCode::UnprocessableEntity, &e),
+    };
+    let pattern = escape_like(q);
+    println!("search_profiles_handler q: {}", q);
+
+    let profiles = fetch_rows(
+        r#"DECLARE @q nvarchar(max) = @P1; DECLARE @pattern nvarchar(max) = @P2; DECLARE @logged int = @P3; DECLARE @limit int = @P4;"#,
+        r#"SELECT TOP (@limit) [UserName],[Bio],[Image],
+        ( SELECT COUNT(*) FROM dbo.Followings F WHERE F.[FollowingId] = Id AND F.FollowerId = @logged ) as Following
+        FROM (
+            SELECT Id, [UserName],[Bio],[Image],
+            CASE WHEN UserName = @q THEN 0 WHEN UserName LIKE @pattern + '%' THEN 1 WHEN UserName LIKE '%' + @pattern + '%' THEN 2 ELSE 3 END AS [Rank]
+            FROM [dbo].[Users]
+        ) AS Ranked
+        WHERE [Rank] < 3 OR ( LEN(@q) >= 3 AND DIFFERENCE(UserName, @q) = 4 )
+        ORDER BY [Rank], LEN(UserName), UserName"#,
+        get_suggested_profile_from_row,
+        &[&q, &pattern.as_str(), &logged_in_user_id, &limit]
+    );
+    send_json(res, &ProfilesResult{ profiles: profiles });
 }
 
-pub fn unfollow_handler(req: Request, res: Response, c: Captures) {
+pub fn unfollow_handler(req: Request, res: Response, p: Params) {
     let (_, logged_in_user_id) = prepare_parameters(req);
 
-    let caps = c.unwrap();
-    let profile = &caps[0].replace("/api/profiles/", "").replace("/follow", "");
+    let profile = p.get("username");
     println!("profile: {}", profile);
 
     process(
         res,
         r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;DELETE TOP (1) from [dbo].[Followings] WHERE [FollowerId] = @P2;"#, PROFILE_SELECT,
         get_profile_from_row,
-        &[&(profile.as_str()), &logged_in_user_id]
+        &[&profile, &logged_in_user_id]
     ); 
 }
 
-pub fn follow_handler(req: Request, res: Response, c: Captures) {
+pub fn follow_handler(req: Request, res: Response, p: Params) {
 
     let (_, logged_in_user_id) = prepare_parameters(req);
 
-    let caps = c.unwrap();
-    let profile = &caps[0].replace("/api/profiles/", "").replace("/follow", "");
+    let profile = p.get("username");
     println!("profile: {}", profile);
 
     process(
@@ -219,15 +294,14 @@ pub fn follow_handler(req: Request, res: Response, c: Captures) {
         r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;INSERT INTO [dbo].[Followings] ([FollowingId] ,[FollowerId])
      SELECT @P2,(SELECT TOP (1) [Id]  FROM [Users] where UserName = @P1) EXCEPT SELECT [FollowingId] ,[FollowerId] from Followings;"#, PROFILE_SELECT,
         get_profile_from_row,
-        &[&(profile.as_str()), &logged_in_user_id]
+        &[&profile, &logged_in_user_id]
     ); 
 }
 
-use unicase::UniCase;
 use hyper::header::{ContentType};
 use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
 
-pub fn authentication_handler(mut req: Request, mut res: Response, _: Captures) {
+pub fn authentication_handler(mut req: Request, mut res: Response, _: Params) {
     let mut body = String::new();
     let _ = req.read_to_string(&mut body);    
     let login : Login = serde_json::from_str(&body).unwrap();    
@@ -236,7 +310,7 @@ pub fn authentication_handler(mut req: Request, mut res: Response, _: Captures)
     {
         let mut sql = Core::new().unwrap();
         let email : &str = &login.user.email;
-        let get_user_cmd = SqlConnection::connect(sql.handle(), CONNECTION_STRING.as_str() )
+        let get_user_cmd = SqlConnection::connect(sql.handle(), SETTINGS.database.connection_string.as_str() )
             .and_then(|conn| conn.query( "SELECT TOP 1 [Email],[Token],[UserName],[Bio],[Image], Id FROM [dbo].[Users] WHERE [Email] = @P1", &[&email] )
             .for_each_row(|row| {
                 let (user_id,stored_hash,result2) = get_user_from_row(row);
@@ -255,12 +329,6 @@ pub fn authentication_handler(mut req: Request, mut res: Response, _: Captures)
                                     }
                                 )
                             );
-                            res.headers_mut().set(
-                                AccessControlAllowOrigin::Any
-                            );
-                            res.headers_mut().set(
-                                AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
-                            );                            
                             res.headers_mut().set(
                                 ContentType(Mime(TopLevel::Application, SubLevel::Json,
                                             vec![(Attr::Charset, Value::Utf8)]))
@@ -437,7 +505,45 @@ fn update_user_test() {
 
 #[cfg(test)]
 #[test]
-#[should_panic]
+fn update_user_if_match_test() {
+    let client = Client::new();
+    let ( user_name, email ) = register_jacob();
+    let jwt = login_jacob( email.to_owned(), JACOB_PASSWORD.to_string() );
+    let url = "http://localhost:6767/api/user";
+
+    let res = client.get(url).header(Authorization(Bearer {token: jwt.to_owned()})).send().unwrap();
+    let read = res.headers.get::<hyper::header::ETag>().unwrap().clone();
+
+    let body = format!(r#"{{"user": {{ "bio":"First tab {}"}}}}"#, user_name);
+    let res = client.put(url)
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .header(hyper::header::IfMatch::Items(vec![read.0.clone()]))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let written = res.headers.get::<hyper::header::ETag>().unwrap().clone();
+    assert!(written != read);
+
+    // the second tab still holds the first version
+    let res = client.put(url)
+        .header(Authorization(Bearer {token: jwt.to_owned()}))
+        .header(hyper::header::IfMatch::Items(vec![read.0]))
+        .body(r#"{"user": { "bio":"Second tab"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::PreconditionFailed);
+    assert_eq!(res.headers.get::<hyper::header::ETag>(), Some(&written));
+
+    let mut res = client.get(url).header(Authorization(Bearer {token: jwt})).send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let user : UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(user.user.bio, Some(format!("First tab {}", user_name)));
+}
+
+#[cfg(test)]
+#[test]
 fn get_current_user_fail_test() {
     let client = Client::new();
 
@@ -449,9 +555,9 @@ fn get_current_user_fail_test() {
     let mut buffer = String::new();
     res.read_to_string(&mut buffer).unwrap(); 
 
-    let registration : UserResult = serde_json::from_str(&buffer).unwrap();   
-    let _ = registration.user;  
-    assert_eq!(res.status, hyper::Ok);
+    assert_eq!(res.status, StatusCode::Unauthorized);
+    let error : InternalError = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(error.errors.body.len(), 1);
 }
 
 
@@ -538,3 +644,35 @@ fn unfollow_test() {
 
     assert_eq!(res.status, hyper::Ok);
 }
+
+#[cfg(test)]
+#[test]
+fn search_profiles_test() {
+    let client = Client::new();
+    let ( user_name, _ ) = register_jacob();
+
+    let mut res = client.get(&format!("http://localhost:6767/api/profiles?q={}", user_name.to_lowercase()))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    assert_eq!(res.status, hyper::Ok);
+
+    let profiles : ProfilesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(profiles.profiles[0].username, user_name);
+
+    let prefix = &user_name[..user_name.rfind('-').unwrap()];
+    let mut res = client.get(&format!("http://localhost:6767/api/profiles?q={}&limit=1", prefix))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap(); 
+    let profiles : ProfilesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(profiles.profiles.len(), 1);
+    assert!(profiles.profiles[0].username.starts_with(prefix));
+
+    let res = client.get("http://localhost:6767/api/profiles")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, StatusCode::UnprocessableEntity);
+}